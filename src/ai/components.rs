@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// What an NPC does, swap it at any time to change its mind
+#[derive(Reflect, FromReflect, Clone, PartialEq, Deserialize)]
+pub enum Behaviour {
+    // Stand still
+    Idle,
+    // Walk to random spots around `center`, resting a moment at each
+    Wander {
+        center: Vec3,
+        radius: f32,
+        pause: f32,
+    },
+    // Walk from waypoint to waypoint, starting over after the last one
+    Patrol {
+        waypoints: Vec<Vec3>,
+        pause: f32,
+    },
+    // Stay within `distance` of the player, running to catch up when far behind
+    Follow {
+        distance: f32,
+    },
+    // Run away from the player when closer than `distance`
+    Flee {
+        distance: f32,
+    },
+}
+
+// Steers a character by setting its Velocity target, like the player's input does
+#[derive(Component, Reflect)]
+pub struct Ai {
+    pub behaviour: Behaviour,
+    // Where the character is currently walking to, for Wander and Patrol
+    pub destination: Option<Vec3>,
+    pub waypoint: usize,
+    // Time left to rest before moving on
+    pub wait_timer: f32,
+}
+
+impl Ai {
+    pub fn new(behaviour: Behaviour) -> Self {
+        Self {
+            behaviour,
+            destination: None,
+            waypoint: 0,
+            wait_timer: 0.0,
+        }
+    }
+}
+
+impl Default for Ai {
+    fn default() -> Self {
+        Self::new(Behaviour::Idle)
+    }
+}
@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+
+pub mod components;
+mod systems;
+
+use components::*;
+use systems::*;
+
+use crate::character::MoveCharacterSet;
+use crate::GameState;
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Register types
+            .register_type::<Ai>()
+            .register_type::<Behaviour>()
+            // On update
+            .add_system(
+                run_ai
+                    .before(MoveCharacterSet)
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
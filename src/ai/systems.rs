@@ -0,0 +1,213 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use super::components::*;
+use crate::character::components::{Movable, Player, Velocity};
+
+// Close enough to a destination to count as arrived
+const ARRIVE_DISTANCE: f32 = 0.2;
+
+pub fn run_ai(
+    mut query: Query<(&mut Ai, &Movable, &Transform, &mut Velocity), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    let player_position = player_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation);
+
+    for (mut ai, movable, transform, mut velocity) in &mut query {
+        velocity.target = steer(
+            &mut ai,
+            movable,
+            transform.translation,
+            player_position,
+            time.delta_seconds(),
+        );
+    }
+}
+
+// The velocity the character should be moving at
+pub fn steer(
+    ai: &mut Ai,
+    movable: &Movable,
+    position: Vec3,
+    player_position: Option<Vec3>,
+    delta: f32,
+) -> Vec3 {
+    let flat = |vector: Vec3| vector * Vec3::new(1.0, 0.0, 1.0);
+
+    match ai.behaviour.clone() {
+        Behaviour::Idle => Vec3::ZERO,
+        Behaviour::Wander {
+            center,
+            radius,
+            pause,
+        } => walk_and_rest(ai, movable, position, pause, delta, |_| {
+            let angle = fastrand::f32() * 2.0 * PI;
+            // Square root, to spread the spots evenly over the circle
+            let distance = fastrand::f32().sqrt() * radius;
+            center + Vec3::new(angle.cos(), 0.0, angle.sin()) * distance
+        }),
+        Behaviour::Patrol { waypoints, pause } => {
+            if waypoints.is_empty() {
+                return Vec3::ZERO;
+            }
+            walk_and_rest(ai, movable, position, pause, delta, |ai| {
+                let waypoint = waypoints[ai.waypoint % waypoints.len()];
+                ai.waypoint = (ai.waypoint + 1) % waypoints.len();
+                waypoint
+            })
+        }
+        Behaviour::Follow { distance } => {
+            let Some(player_position) = player_position else {
+                return Vec3::ZERO;
+            };
+            let offset = flat(player_position - position);
+            let gap = offset.length();
+            if gap <= distance {
+                return Vec3::ZERO;
+            }
+            let speed = if gap > distance * 3.0 {
+                movable.run_speed
+            } else {
+                movable.walk_speed
+            };
+            offset / gap * speed
+        }
+        Behaviour::Flee { distance } => {
+            let Some(player_position) = player_position else {
+                return Vec3::ZERO;
+            };
+            let offset = flat(position - player_position);
+            let gap = offset.length();
+            if gap >= distance {
+                return Vec3::ZERO;
+            }
+            // Standing right on top of each other, any way out will do
+            let away = offset.try_normalize().unwrap_or(Vec3::X);
+            away * movable.run_speed
+        }
+    }
+}
+
+// Walk to the destination, rest there, then pick the next one
+fn walk_and_rest(
+    ai: &mut Ai,
+    movable: &Movable,
+    position: Vec3,
+    pause: f32,
+    delta: f32,
+    next_destination: impl FnOnce(&mut Ai) -> Vec3,
+) -> Vec3 {
+    if ai.wait_timer > 0.0 {
+        ai.wait_timer -= delta;
+        return Vec3::ZERO;
+    }
+
+    let destination = match ai.destination {
+        Some(destination) => destination,
+        None => {
+            let destination = next_destination(ai);
+            ai.destination = Some(destination);
+            destination
+        }
+    };
+
+    let offset = (destination - position) * Vec3::new(1.0, 0.0, 1.0);
+    let distance = offset.length();
+    if distance < ARRIVE_DISTANCE {
+        ai.destination = None;
+        ai.wait_timer = pause;
+        return Vec3::ZERO;
+    }
+    offset / distance * movable.arrival_speed(movable.walk_speed, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steer_once(ai: &mut Ai, position: Vec3, player_position: Option<Vec3>) -> Vec3 {
+        steer(ai, &Movable::default(), position, player_position, 0.1)
+    }
+
+    #[test]
+    fn idle_stands_still() {
+        let mut ai = Ai::new(Behaviour::Idle);
+        assert_eq!(steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X)), Vec3::ZERO);
+    }
+
+    #[test]
+    fn wander_stays_within_its_radius() {
+        let center = Vec3::new(1.0, 0.0, 1.0);
+        let mut ai = Ai::new(Behaviour::Wander {
+            center,
+            radius: 2.0,
+            pause: 0.0,
+        });
+
+        for _ in 0..50 {
+            ai.destination = None;
+            steer_once(&mut ai, Vec3::splat(10.0), None);
+            let destination = ai.destination.unwrap();
+            assert!(destination.distance(center) <= 2.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn patrol_visits_the_waypoints_in_order_and_rests_at_each() {
+        let waypoints = vec![Vec3::X * 3.0, Vec3::Z * 3.0];
+        let mut ai = Ai::new(Behaviour::Patrol {
+            waypoints: waypoints.clone(),
+            pause: 0.45,
+        });
+
+        let velocity = steer_once(&mut ai, Vec3::ZERO, None);
+        assert!(velocity.normalize().abs_diff_eq(Vec3::X, 1e-5));
+
+        // Arrive, rest, then head for the next waypoint
+        assert_eq!(steer_once(&mut ai, waypoints[0], None), Vec3::ZERO);
+        assert!(ai.wait_timer > 0.0);
+        for _ in 0..5 {
+            assert_eq!(steer_once(&mut ai, waypoints[0], None), Vec3::ZERO);
+        }
+        steer_once(&mut ai, waypoints[0], None);
+        assert_eq!(ai.destination, Some(waypoints[1]));
+
+        steer_once(&mut ai, waypoints[1], None);
+        ai.wait_timer = 0.0;
+        steer_once(&mut ai, waypoints[1], None);
+        assert_eq!(ai.destination, Some(waypoints[0]));
+    }
+
+    #[test]
+    fn follow_catches_up_and_stops_close_by() {
+        let movable = Movable::default();
+        let mut ai = Ai::new(Behaviour::Follow { distance: 1.0 });
+
+        let far = steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X * 10.0));
+        assert!(far.abs_diff_eq(Vec3::X * movable.run_speed, 1e-5));
+        let near = steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X * 2.0));
+        assert!(near.abs_diff_eq(Vec3::X * movable.walk_speed, 1e-5));
+        assert_eq!(
+            steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X * 0.5)),
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn flee_runs_away_when_the_player_is_close() {
+        let movable = Movable::default();
+        let mut ai = Ai::new(Behaviour::Flee { distance: 3.0 });
+
+        let close = steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X));
+        assert!(close.abs_diff_eq(Vec3::NEG_X * movable.run_speed, 1e-5));
+        assert_eq!(
+            steer_once(&mut ai, Vec3::ZERO, Some(Vec3::X * 5.0)),
+            Vec3::ZERO
+        );
+    }
+}
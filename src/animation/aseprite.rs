@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_mod_aseprite::*;
+
+use super::components::Direction;
+use super::components::*;
+
+// Aseprite allows frames of 0 ms, but a zero length frame timer would panic
+const MIN_FRAME_DURATION: f32 = 0.001;
+
+// Build the lookup table used by AnimatedCharacter from the tags of an Aseprite file.
+// Tags are expected to be named `<state>_<direction>`, e.g. `walk_down` or `idle_left`.
+pub fn build_animations_from_aseprite(
+    info: &AsepriteInfo,
+) -> HashMap<(AnimationState, Direction), Animation> {
+    let mut animations = HashMap::new();
+
+    for (name, tag) in info.tags.iter() {
+        let Some(key) = parse_tag_name(name) else {
+            warn!(
+                "Ignoring Aseprite tag '{}', expected '<state>_<direction>'",
+                name
+            );
+            continue;
+        };
+
+        let mut frames: Vec<usize> = (tag.frames.start..tag.frames.end)
+            .map(|frame| frame as usize)
+            .collect();
+        let mut mode = PlaybackMode::Loop;
+        match tag.animation_direction {
+            AsepriteAnimationDirection::Forward => (),
+            AsepriteAnimationDirection::Reverse => frames.reverse(),
+            AsepriteAnimationDirection::PingPong => mode = PlaybackMode::PingPong,
+        }
+        if frames.is_empty() {
+            continue;
+        }
+
+        let Some(frame_durations) = frames
+            .iter()
+            .map(|&frame| {
+                info.frame_infos
+                    .get(frame)
+                    .map(|frame_info| (frame_info.delay_ms as f32 / 1000.0).max(MIN_FRAME_DURATION))
+            })
+            .collect()
+        else {
+            warn!(
+                "Ignoring Aseprite tag '{}', its frames go past the last frame",
+                name
+            );
+            continue;
+        };
+        animations.insert(
+            key,
+            Animation {
+                mode,
+                ..Animation::with_frame_durations(frames, frame_durations)
+            },
+        );
+    }
+
+    animations
+}
+
+fn parse_tag_name(name: &str) -> Option<(AnimationState, Direction)> {
+    let (state, direction) = name.rsplit_once('_')?;
+    Some((state.parse().ok()?, direction.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::animation::systems::step_animation;
+
+    fn tag(name: &str, frames: Range<u16>) -> (String, AsepriteTag) {
+        (
+            name.to_string(),
+            AsepriteTag {
+                frames,
+                animation_direction: AsepriteAnimationDirection::Forward,
+                name: name.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn tags_past_the_last_frame_are_skipped() {
+        let info = AsepriteInfo {
+            dimensions: (20, 28),
+            tags: [tag("idle_down", 0..1), tag("walk_down", 1..9)]
+                .into_iter()
+                .collect(),
+            slices: HashMap::new(),
+            frame_count: 2,
+            frame_infos: vec![AsepriteFrameInfo { delay_ms: 100 }; 2],
+        };
+
+        let animations = build_animations_from_aseprite(&info);
+
+        assert_eq!(animations.len(), 1);
+        assert!(animations.contains_key(&(AnimationState::Idle, Direction::Down)));
+    }
+
+    #[test]
+    fn zero_length_frames_are_still_shown() {
+        let info = AsepriteInfo {
+            dimensions: (20, 28),
+            tags: [tag("walk_down", 0..2)].into_iter().collect(),
+            slices: HashMap::new(),
+            frame_count: 2,
+            frame_infos: vec![
+                AsepriteFrameInfo { delay_ms: 0 },
+                AsepriteFrameInfo { delay_ms: 100 },
+            ],
+        };
+
+        let mut animations = build_animations_from_aseprite(&info);
+        let walk_down = animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap();
+        assert!(walk_down.frame_duration(0) > 0.0);
+        assert_eq!(
+            step_animation(walk_down, Duration::from_millis(50)),
+            Some(1)
+        );
+    }
+}
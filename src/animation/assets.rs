@@ -0,0 +1,121 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::Deserialize;
+
+use super::components::Direction;
+use super::components::*;
+
+// Describes every animation of a character, loaded from a `.anim.ron` file
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "8c1f3a52-6f0e-4d8b-9a57-2f4b1c7e9d30"]
+pub struct AnimationSet {
+    pub animations: Vec<AnimationDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct AnimationDefinition {
+    pub state: AnimationState,
+    pub direction: Direction,
+    // Indices into the character's texture atlas
+    pub frames: Vec<usize>,
+    // Seconds each frame is shown
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub markers: Vec<FrameMarker>,
+    #[serde(default)]
+    pub mode: PlaybackMode,
+    // Movement speed the animation was drawn for, to keep feet from sliding
+    #[serde(default)]
+    pub reference_speed: Option<f32>,
+}
+
+fn default_speed() -> f32 {
+    0.1
+}
+
+impl AnimationSet {
+    // Every frame has to be shown for some time, a zero length frame timer would panic
+    pub fn validate(&self) -> Result<(), String> {
+        for definition in self.animations.iter() {
+            if definition.speed <= 0.0 || !definition.speed.is_finite() {
+                return Err(format!(
+                    "{} {} has a speed of {}, it has to be above 0",
+                    definition.state, definition.direction, definition.speed
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Build the lookup table used by AnimatedCharacter
+    pub fn build_animations(&self) -> HashMap<(AnimationState, Direction), Animation> {
+        self.animations
+            .iter()
+            .filter(|definition| !definition.frames.is_empty())
+            .map(|definition| {
+                (
+                    (definition.state, definition.direction),
+                    Animation {
+                        markers: definition.markers.clone(),
+                        mode: definition.mode,
+                        reference_speed: definition.reference_speed,
+                        ..Animation::new(definition.frames.clone(), definition.speed)
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct AnimationSetLoader;
+
+impl AssetLoader for AnimationSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let animation_set = ron::de::from_bytes::<AnimationSet>(bytes)?;
+            animation_set.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(animation_set));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn character_animation_set_parses() {
+        let animation_set: AnimationSet =
+            ron::de::from_str(include_str!("../../assets/Character.anim.ron")).unwrap();
+        let animations = animation_set.build_animations();
+
+        assert_eq!(animations.len(), 40);
+        let walk_down = &animations[&(AnimationState::Walk, Direction::Down)];
+        assert_eq!(walk_down.frames, vec![4, 8, 12, 16, 20, 24, 28, 32]);
+        assert_eq!(walk_down.markers.len(), 2);
+    }
+
+    #[test]
+    fn speeds_have_to_be_above_zero() {
+        for speed in ["0.0", "-0.1"] {
+            let animation_set: AnimationSet = ron::de::from_str(&format!(
+                "(animations: [(state: Idle, direction: Down, frames: [0], speed: {})])",
+                speed
+            ))
+            .unwrap();
+            assert!(animation_set.validate().is_err());
+        }
+    }
+}
@@ -1,28 +1,97 @@
 use bevy::prelude::*;
 use bevy::utils::HashMap;
 use core::fmt;
+use serde::Deserialize;
 use std::f32::EPSILON;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Reflect, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Reflect, FromReflect, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum AnimationState {
     Idle,
     Walk,
+    Run,
+    Attack,
+    Hurt,
+    Death,
+    Jump,
+    Fall,
+    Interact,
+    Sit,
+    // Game specific states, without having to extend this enum,
+    // e.g. `const DANCE: AnimationState = AnimationState::Custom(0);`
+    Custom(u16),
 }
 impl fmt::Display for AnimationState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AnimationState::Idle => write!(f, "Idle"),
             AnimationState::Walk => write!(f, "Walk"),
+            AnimationState::Run => write!(f, "Run"),
+            AnimationState::Attack => write!(f, "Attack"),
+            AnimationState::Hurt => write!(f, "Hurt"),
+            AnimationState::Death => write!(f, "Death"),
+            AnimationState::Jump => write!(f, "Jump"),
+            AnimationState::Fall => write!(f, "Fall"),
+            AnimationState::Interact => write!(f, "Interact"),
+            AnimationState::Sit => write!(f, "Sit"),
+            AnimationState::Custom(id) => write!(f, "Custom{}", id),
         }
     }
 }
+impl FromStr for AnimationState {
+    type Err = ();
 
-#[derive(Reflect, Clone, Copy, PartialEq, Eq, Hash)]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "idle" => Ok(AnimationState::Idle),
+            "walk" => Ok(AnimationState::Walk),
+            "run" => Ok(AnimationState::Run),
+            "attack" => Ok(AnimationState::Attack),
+            "hurt" => Ok(AnimationState::Hurt),
+            "death" => Ok(AnimationState::Death),
+            "jump" => Ok(AnimationState::Jump),
+            "fall" => Ok(AnimationState::Fall),
+            "interact" => Ok(AnimationState::Interact),
+            "sit" => Ok(AnimationState::Sit),
+            _ => match s.strip_prefix("custom") {
+                Some(id) => id.parse().map(AnimationState::Custom).map_err(|_| ()),
+                None => Err(()),
+            },
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum Direction {
     Down,
     Right,
     Up,
     Left,
+    DownRight,
+    UpRight,
+    UpLeft,
+    DownLeft,
+}
+impl Direction {
+    // Directions in the order they're seen when circling around a character, starting in front
+    pub const FOUR: [Direction; 4] = [
+        Direction::Down,
+        Direction::Left,
+        Direction::Up,
+        Direction::Right,
+    ];
+    pub const EIGHT: [Direction; 8] = [
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+    ];
 }
 impl fmt::Display for Direction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -31,15 +100,69 @@ impl fmt::Display for Direction {
             Direction::Right => write!(f, "Right"),
             Direction::Up => write!(f, "Up"),
             Direction::Left => write!(f, "Left"),
+            Direction::DownRight => write!(f, "DownRight"),
+            Direction::UpRight => write!(f, "UpRight"),
+            Direction::UpLeft => write!(f, "UpLeft"),
+            Direction::DownLeft => write!(f, "DownLeft"),
         }
     }
 }
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "down" => Ok(Direction::Down),
+            "right" => Ok(Direction::Right),
+            "up" => Ok(Direction::Up),
+            "left" => Ok(Direction::Left),
+            "downright" => Ok(Direction::DownRight),
+            "upright" => Ok(Direction::UpRight),
+            "upleft" => Ok(Direction::UpLeft),
+            "downleft" => Ok(Direction::DownLeft),
+            _ => Err(()),
+        }
+    }
+}
+
+// What happens when an animation reaches its last frame
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum PlaybackMode {
+    // Start over from the first frame
+    #[default]
+    Loop,
+    // Play once, then go to the queued state or rewind to the first frame and stop
+    Once,
+    // Play back and forth
+    PingPong,
+    // Play once, then go to the queued state or hold the last frame
+    ClampForever,
+}
+
+// A named point in an animation, e.g. a footstep, that fires an AnimationMarkerReached event
+#[derive(Reflect, FromReflect, Clone, PartialEq, Deserialize)]
+pub struct FrameMarker {
+    // Index into the animation's frames, not the atlas
+    pub frame: usize,
+    pub name: String,
+}
 
 #[derive(Reflect)]
 pub struct Animation {
     pub frames: Vec<usize>,
     pub current: usize,
     pub speed: f32,
+    // Seconds per frame, overriding speed when set (e.g. from Aseprite)
+    pub frame_durations: Vec<f32>,
+    pub markers: Vec<FrameMarker>,
+    pub mode: PlaybackMode,
+    // Movement speed, in metres per second, the animation was drawn for.
+    // When set, playback is scaled by how fast the character actually moves
+    pub reference_speed: Option<f32>,
+    // Whether a PingPong animation is currently playing backwards
+    pub reversing: bool,
+    // Set once a Once or ClampForever animation has played through
+    pub finished: bool,
     pub timer: Timer,
 }
 impl Default for Animation {
@@ -48,22 +171,103 @@ impl Default for Animation {
             frames: Vec::new(),
             current: 0,
             speed: 0.1,
+            frame_durations: Vec::new(),
+            markers: Vec::new(),
+            mode: PlaybackMode::Loop,
+            reference_speed: None,
+            reversing: false,
+            finished: false,
             timer: Timer::from_seconds(0.1, TimerMode::Repeating),
         }
     }
 }
 
+impl Animation {
+    pub fn new(frames: Vec<usize>, speed: f32) -> Self {
+        Self {
+            frames,
+            current: 0,
+            speed,
+            frame_durations: Vec::new(),
+            markers: Vec::new(),
+            mode: PlaybackMode::Loop,
+            reference_speed: None,
+            reversing: false,
+            finished: false,
+            timer: Timer::from_seconds(speed, TimerMode::Repeating),
+        }
+    }
+
+    pub fn with_frame_durations(frames: Vec<usize>, frame_durations: Vec<f32>) -> Self {
+        let mut animation = Self {
+            frames,
+            frame_durations,
+            ..default()
+        };
+        animation.set_frame(0);
+        animation
+    }
+
+    // How many seconds the given frame is shown for
+    pub fn frame_duration(&self, index: usize) -> f32 {
+        self.frame_durations
+            .get(index)
+            .copied()
+            .unwrap_or(self.speed)
+    }
+
+    // Jump to a frame, and make the timer match that frame's duration
+    pub fn set_frame(&mut self, index: usize) {
+        self.current = index;
+        let duration = self.frame_duration(index);
+        self.timer.set_duration(Duration::from_secs_f32(duration));
+    }
+
+    // The sprite to show for the current frame, if there are any frames
+    pub fn sprite_index(&self) -> Option<usize> {
+        self.frames.get(self.current).copied()
+    }
+
+    // Back to the first frame, ready to be played again
+    pub fn restart(&mut self) {
+        self.set_frame(0);
+        self.timer.reset();
+        self.reversing = false;
+        self.finished = false;
+    }
+
+    // How much faster than normal to play, for a character with the given playback rate and speed
+    pub fn playback_scale(&self, playback_rate: f32, movement_speed: f32) -> f32 {
+        let movement_scale = match self.reference_speed {
+            Some(reference_speed) if reference_speed > 0.0 => movement_speed / reference_speed,
+            _ => 1.0,
+        };
+        (playback_rate * movement_scale).max(0.0)
+    }
+
+    pub fn markers_on_current_frame(&self) -> impl Iterator<Item = &FrameMarker> {
+        self.markers
+            .iter()
+            .filter(|marker| marker.frame == self.current)
+    }
+}
+
 impl PartialEq for Animation {
     fn eq(&self, other: &Self) -> bool {
         self.frames == other.frames
             && self.current == other.current
             && (self.speed - other.speed) < EPSILON
+            && self.frame_durations == other.frame_durations
+            && self.markers == other.markers
+            && self.mode == other.mode
+            && self.reference_speed == other.reference_speed
             && self.timer.duration() == other.timer.duration()
     }
 }
 
 // Perhaps this could be BillboardEntity or something?
 #[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct AnimatedCharacter {
     // The orientation that the character is facing
     pub heading: Vec3,
@@ -71,6 +275,19 @@ pub struct AnimatedCharacter {
     pub direction: Direction,
     // What animation the character is performing
     pub animation_state: AnimationState,
+    // What animation to play once the current one-shot animation finishes
+    pub queued_state: Option<AnimationState>,
+    // Opt-in to diagonal directions, for characters with 8-way spritesheets
+    pub eight_directions: bool,
+    // Multiplier on how fast all animations of this character play
+    pub playback_rate: f32,
+    // Horizontal speed, in metres per second, measured from the Transform
+    pub movement_speed: f32,
+    // Fastest the character can move. Anything faster is a teleport, like loading a save,
+    // and is capped so it doesn't speed up the animation
+    pub max_movement_speed: f32,
+    #[reflect(ignore)]
+    pub last_position: Option<Vec3>,
     #[reflect(ignore)]
     pub animations: HashMap<(AnimationState, Direction), Animation>,
 }
@@ -81,10 +298,26 @@ impl Default for AnimatedCharacter {
             heading: Vec3::Z,
             direction: Direction::Down,
             animation_state: AnimationState::Idle,
+            queued_state: None,
+            eight_directions: false,
+            playback_rate: 1.0,
+            movement_speed: 0.0,
+            max_movement_speed: f32::MAX,
+            last_position: None,
             animations: HashMap::new(),
         }
     }
 }
 
+impl AnimatedCharacter {
+    pub fn directions(&self) -> &'static [Direction] {
+        if self.eight_directions {
+            &Direction::EIGHT
+        } else {
+            &Direction::FOUR
+        }
+    }
+}
+
 #[derive(Component, Reflect, Default)]
 pub struct TurnTowardCamera(pub bool);
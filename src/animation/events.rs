@@ -0,0 +1,19 @@
+use bevy::prelude::*;
+
+use super::components::Direction;
+use super::components::*;
+
+// Sent when an animation steps onto a frame with a FrameMarker
+pub struct AnimationMarkerReached {
+    pub entity: Entity,
+    pub state: AnimationState,
+    pub direction: Direction,
+    pub marker: String,
+}
+
+// Sent when a Once or ClampForever animation has played its last frame
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub state: AnimationState,
+    pub direction: Direction,
+}
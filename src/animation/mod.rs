@@ -1,13 +1,24 @@
 use bevy::prelude::*;
 
+pub mod aseprite;
+pub mod assets;
 pub mod components;
+pub mod events;
+pub mod state_machine;
 pub mod systems;
 
+use assets::*;
 use components::*;
+use events::*;
 use systems::*;
 
 use crate::GameState;
 
+// State machines are evaluated and sprites stepped in this set, so anything setting the
+// animation parameters runs before it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnimateCharactersSet;
+
 pub struct AnimationPlugin;
 
 impl Plugin for AnimationPlugin {
@@ -16,15 +27,275 @@ impl Plugin for AnimationPlugin {
             // Register types
             .register_type::<TurnTowardCamera>()
             .register_type::<AnimatedCharacter>()
+            .register_type::<AnimationState>()
+            .register_type::<Option<AnimationState>>()
+            .register_type::<components::Direction>()
+            // Events
+            .add_event::<AnimationMarkerReached>()
+            .add_event::<AnimationFinished>()
+            // Assets
+            .add_asset::<AnimationSet>()
+            .init_asset_loader::<AnimationSetLoader>()
             // On update
             .add_systems(
                 (
+                    apply_animation_sets,
+                    apply_aseprite_animations,
+                    evaluate_animation_state_machines,
+                    measure_movement_speed,
                     turning_toward_camera,
                     update_character_direction,
                     animate_sprite_system,
                 )
                     .chain()
+                    .in_set(AnimateCharactersSet)
                     .in_set(OnUpdate(GameState::Playing)),
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy_mod_aseprite::{Aseprite, AsepritePlugin};
+    use bevy_sprite3d::AtlasSprite3dComponent;
+
+    use super::components::Direction;
+    use super::*;
+    use crate::testing::{advance, playing_app};
+
+    // A headless app running the animation systems
+    fn test_app() -> App {
+        let mut app = playing_app();
+        app.add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_asset::<TextureAtlas>()
+            .add_plugin(AsepritePlugin)
+            .add_plugin(AnimationPlugin);
+        app
+    }
+
+    fn spawn_camera(app: &mut App, position: Vec3, target: Vec3) -> Entity {
+        app.world
+            .spawn((
+                Camera::default(),
+                Transform::from_translation(position).looking_at(target, Vec3::Y),
+            ))
+            .id()
+    }
+
+    fn spawn_walking_character(app: &mut App, position: Vec3, heading: Vec3) -> Entity {
+        app.world
+            .spawn((
+                AnimatedCharacter {
+                    heading,
+                    animation_state: AnimationState::Walk,
+                    animations: systems::tests::character_animations(),
+                    ..default()
+                },
+                AtlasSprite3dComponent {
+                    index: 0,
+                    atlas: Vec::new(),
+                },
+                Transform::from_translation(position),
+                TurnTowardCamera(true),
+            ))
+            .id()
+    }
+
+    fn sprite_index(app: &App, entity: Entity) -> usize {
+        app.world
+            .get::<AtlasSprite3dComponent>(entity)
+            .unwrap()
+            .index
+    }
+
+    fn direction(app: &App, entity: Entity) -> Direction {
+        app.world
+            .get::<AnimatedCharacter>(entity)
+            .unwrap()
+            .direction
+    }
+
+    #[test]
+    fn walk_animation_steps_over_time() {
+        let mut app = test_app();
+        spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let character = spawn_walking_character(&mut app, Vec3::ZERO, Vec3::Z);
+        advance(&mut app, 0.0);
+
+        assert!(direction(&app, character) == Direction::Down);
+        for step in 1..=10 {
+            advance(&mut app, 0.1);
+            assert_eq!(sprite_index(&app, character), (step % 8) * 4 + 4);
+        }
+    }
+
+    #[test]
+    fn moving_the_camera_changes_the_shown_direction() {
+        let mut app = test_app();
+        let camera = spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let character = spawn_walking_character(&mut app, Vec3::ZERO, Vec3::Z);
+        advance(&mut app, 0.0);
+        advance(&mut app, 0.1);
+        advance(&mut app, 0.1);
+        assert_eq!(sprite_index(&app, character), 12);
+
+        *app.world.get_mut::<Transform>(camera).unwrap() =
+            Transform::from_xyz(10.0, 2.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y);
+        advance(&mut app, 0.0);
+
+        assert!(direction(&app, character) == Direction::Left);
+        assert_eq!(sprite_index(&app, character), 2 * 4 + 4 + 3);
+    }
+
+    #[test]
+    fn direction_is_relative_to_the_character_position() {
+        let mut app = test_app();
+        spawn_camera(
+            &mut app,
+            Vec3::new(40.0, 2.0, 10.0),
+            Vec3::new(40.0, 0.0, 0.0),
+        );
+        let character = spawn_walking_character(&mut app, Vec3::new(40.0, 0.0, 0.0), Vec3::Z);
+        advance(&mut app, 0.0);
+
+        assert!(direction(&app, character) == Direction::Down);
+    }
+
+    #[test]
+    fn markers_fire_when_their_frame_is_reached() {
+        let mut app = test_app();
+        spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let character = spawn_walking_character(&mut app, Vec3::ZERO, Vec3::Z);
+        app.world
+            .get_mut::<AnimatedCharacter>(character)
+            .unwrap()
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap()
+            .markers = vec![FrameMarker {
+            frame: 2,
+            name: "footstep".to_string(),
+        }];
+        advance(&mut app, 0.0);
+
+        let mut reached = Vec::new();
+        for _ in 0..8 {
+            advance(&mut app, 0.1);
+            let events = app.world.resource::<Events<AnimationMarkerReached>>();
+            reached.extend(
+                events
+                    .iter_current_update_events()
+                    .map(|event| (event.entity, event.marker.clone())),
+            );
+        }
+
+        assert_eq!(reached, vec![(character, "footstep".to_string())]);
+    }
+
+    #[test]
+    fn one_shot_animation_finishes_into_the_queued_state() {
+        let mut app = test_app();
+        spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let character = spawn_walking_character(&mut app, Vec3::ZERO, Vec3::Z);
+        {
+            let mut animated_character = app.world.get_mut::<AnimatedCharacter>(character).unwrap();
+            animated_character
+                .animations
+                .get_mut(&(AnimationState::Walk, Direction::Down))
+                .unwrap()
+                .mode = PlaybackMode::Once;
+            animated_character.queued_state = Some(AnimationState::Idle);
+        }
+        advance(&mut app, 0.0);
+
+        let mut finished = 0;
+        for _ in 0..8 {
+            advance(&mut app, 0.1);
+            let events = app.world.resource::<Events<AnimationFinished>>();
+            finished += events.iter_current_update_events().count();
+        }
+
+        assert_eq!(finished, 1);
+        let animated_character = app.world.get::<AnimatedCharacter>(character).unwrap();
+        assert!(animated_character.animation_state == AnimationState::Idle);
+        assert!(animated_character.queued_state.is_none());
+        assert_eq!(sprite_index(&app, character), 0);
+    }
+
+    #[test]
+    fn animations_are_built_from_a_loaded_aseprite() {
+        let mut app = test_app();
+        spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let aseprite: Handle<Aseprite> = app
+            .world
+            .resource::<AssetServer>()
+            .load("Traveller.aseprite");
+        let character = app
+            .world
+            .spawn((
+                AnimatedCharacter {
+                    animation_state: AnimationState::Walk,
+                    ..default()
+                },
+                aseprite,
+                AtlasSprite3dComponent {
+                    index: 0,
+                    atlas: Vec::new(),
+                },
+                Transform::default(),
+            ))
+            .id();
+
+        // The file is loaded in the background
+        for _ in 0..200 {
+            advance(&mut app, 0.0);
+            let animated_character = app.world.get::<AnimatedCharacter>(character).unwrap();
+            if !animated_character.animations.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let animated_character = app.world.get::<AnimatedCharacter>(character).unwrap();
+        assert_eq!(animated_character.animations.len(), 8);
+        let walk_left = &animated_character.animations[&(AnimationState::Walk, Direction::Left)];
+        assert_eq!(walk_left.frames, (28..36).collect::<Vec<_>>());
+        assert_eq!(walk_left.frame_durations, vec![0.1; 8]);
+        assert_eq!(sprite_index(&app, character), 4);
+    }
+
+    #[test]
+    fn teleporting_doesnt_speed_up_the_animation() {
+        let mut app = test_app();
+        spawn_camera(&mut app, Vec3::new(0.0, 2.0, 10.0), Vec3::ZERO);
+        let character = spawn_walking_character(&mut app, Vec3::ZERO, Vec3::Z);
+        app.world
+            .get_mut::<AnimatedCharacter>(character)
+            .unwrap()
+            .max_movement_speed = 5.0;
+        advance(&mut app, 0.1);
+
+        app.world
+            .get_mut::<Transform>(character)
+            .unwrap()
+            .translation = Vec3::new(0.1, 0.0, 0.0);
+        advance(&mut app, 0.1);
+        let movement_speed = |app: &App| {
+            app.world
+                .get::<AnimatedCharacter>(character)
+                .unwrap()
+                .movement_speed
+        };
+        assert!((movement_speed(&app) - 1.0).abs() < 1e-4);
+
+        app.world
+            .get_mut::<Transform>(character)
+            .unwrap()
+            .translation = Vec3::new(40.0, 0.0, 0.0);
+        advance(&mut app, 0.1);
+        assert_eq!(movement_speed(&app), 5.0);
+    }
+}
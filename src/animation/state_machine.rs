@@ -0,0 +1,350 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::components::*;
+
+// Set by gameplay code, the state machine picks the animation from these
+#[derive(Clone, Default)]
+pub struct AnimationParameters {
+    // Current movement speed, in metres per second
+    pub speed: f32,
+    // Upwards is positive, in metres per second
+    pub vertical_speed: f32,
+    pub grounded: bool,
+    // Set for a frame to play the attack or interact animation
+    pub attacking: bool,
+    pub interacting: bool,
+    pub sitting: bool,
+    // Set for a frame to flinch, interrupting anything but dying
+    pub hurt: bool,
+    pub dead: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationCondition {
+    SpeedAbove(f32),
+    SpeedBelow(f32),
+    Grounded,
+    Airborne,
+    Rising,
+    Falling,
+    Attacking,
+    NotAttacking,
+    Interacting,
+    Sitting,
+    NotSitting,
+    Hurt,
+    Dead,
+    Alive,
+    // The current animation has played through, for Once and ClampForever animations
+    Finished,
+}
+
+impl AnimationCondition {
+    pub fn is_met(&self, parameters: &AnimationParameters, finished: bool) -> bool {
+        match *self {
+            AnimationCondition::SpeedAbove(speed) => parameters.speed > speed,
+            AnimationCondition::SpeedBelow(speed) => parameters.speed <= speed,
+            AnimationCondition::Grounded => parameters.grounded,
+            AnimationCondition::Airborne => !parameters.grounded,
+            AnimationCondition::Rising => parameters.vertical_speed > 0.0,
+            AnimationCondition::Falling => parameters.vertical_speed <= 0.0,
+            AnimationCondition::Attacking => parameters.attacking,
+            AnimationCondition::NotAttacking => !parameters.attacking,
+            AnimationCondition::Interacting => parameters.interacting,
+            AnimationCondition::Sitting => parameters.sitting,
+            AnimationCondition::NotSitting => !parameters.sitting,
+            AnimationCondition::Hurt => parameters.hurt,
+            AnimationCondition::Dead => parameters.dead,
+            AnimationCondition::Alive => !parameters.dead,
+            AnimationCondition::Finished => finished,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AnimationTransition {
+    // The state to transition from, or None for any state
+    pub from: Option<AnimationState>,
+    pub to: AnimationState,
+    // All of these have to be met
+    pub conditions: Vec<AnimationCondition>,
+    // When several transitions are possible, the highest priority wins
+    pub priority: i32,
+}
+
+impl AnimationTransition {
+    pub fn new(to: AnimationState, conditions: Vec<AnimationCondition>) -> Self {
+        Self {
+            from: None,
+            to,
+            conditions,
+            priority: 0,
+        }
+    }
+
+    pub fn from(mut self, from: AnimationState) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+// Picks the animation state of an AnimatedCharacter every frame, based on its parameters
+#[derive(Component)]
+pub struct AnimationStateMachine {
+    pub parameters: AnimationParameters,
+    pub transitions: Vec<AnimationTransition>,
+    // States that can only be left before they finish by a transition with at least this priority
+    pub interrupt_priorities: HashMap<AnimationState, i32>,
+}
+
+impl AnimationStateMachine {
+    // Idle, walk and run based on speed, jump and fall while in the air, sit down when
+    // standing still, with attacks and interactions that play through before moving again.
+    // Getting hurt interrupts those, and dying interrupts everything and stays down
+    pub fn locomotion(run_speed_threshold: f32) -> Self {
+        use AnimationCondition::*;
+
+        Self {
+            parameters: AnimationParameters {
+                grounded: true,
+                ..default()
+            },
+            transitions: vec![
+                AnimationTransition::new(
+                    AnimationState::Idle,
+                    vec![SpeedBelow(0.1), Grounded, NotAttacking, NotSitting, Alive],
+                ),
+                AnimationTransition::new(
+                    AnimationState::Sit,
+                    vec![SpeedBelow(0.1), Grounded, NotAttacking, Sitting, Alive],
+                ),
+                AnimationTransition::new(
+                    AnimationState::Walk,
+                    vec![
+                        SpeedAbove(0.1),
+                        SpeedBelow(run_speed_threshold),
+                        Grounded,
+                        NotAttacking,
+                        Alive,
+                    ],
+                ),
+                AnimationTransition::new(
+                    AnimationState::Run,
+                    vec![
+                        SpeedAbove(run_speed_threshold),
+                        Grounded,
+                        NotAttacking,
+                        Alive,
+                    ],
+                ),
+                AnimationTransition::new(
+                    AnimationState::Jump,
+                    vec![Airborne, Rising, NotAttacking, Alive],
+                ),
+                AnimationTransition::new(
+                    AnimationState::Fall,
+                    vec![Airborne, Falling, NotAttacking, Alive],
+                ),
+                AnimationTransition::new(AnimationState::Attack, vec![Attacking, Alive])
+                    .with_priority(10),
+                AnimationTransition::new(
+                    AnimationState::Interact,
+                    vec![Interacting, Grounded, Alive],
+                )
+                .with_priority(10),
+                AnimationTransition::new(AnimationState::Hurt, vec![Hurt, Alive]).with_priority(20),
+                AnimationTransition::new(AnimationState::Death, vec![Dead]).with_priority(30),
+            ],
+            interrupt_priorities: HashMap::from([
+                (AnimationState::Attack, 10),
+                (AnimationState::Interact, 10),
+                (AnimationState::Hurt, 20),
+                (AnimationState::Death, 30),
+            ]),
+        }
+    }
+
+    // The state to transition to, if any
+    pub fn next_state(&self, current: AnimationState, finished: bool) -> Option<AnimationState> {
+        let interrupt_priority = if finished {
+            None
+        } else {
+            self.interrupt_priorities.get(&current).copied()
+        };
+
+        let mut best: Option<&AnimationTransition> = None;
+        for transition in self.transitions.iter() {
+            if transition.to == current
+                || transition.from.map_or(false, |from| from != current)
+                || interrupt_priority.map_or(false, |priority| transition.priority < priority)
+                || !transition
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.is_met(&self.parameters, finished))
+            {
+                continue;
+            }
+            // The first declared transition wins a tie
+            if best.map_or(true, |best| transition.priority > best.priority) {
+                best = Some(transition);
+            }
+        }
+
+        best.map(|transition| transition.to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnimationCondition::*;
+    use super::*;
+    use crate::input::actions::{Action, ActionState};
+
+    fn machine(speed: f32, attacking: bool) -> AnimationStateMachine {
+        let mut machine = AnimationStateMachine::locomotion(3.5);
+        machine.parameters.speed = speed;
+        machine.parameters.attacking = attacking;
+        machine
+    }
+
+    #[test]
+    fn speed_picks_the_locomotion_state() {
+        assert!(
+            machine(0.0, false).next_state(AnimationState::Walk, false)
+                == Some(AnimationState::Idle)
+        );
+        assert!(
+            machine(2.0, false).next_state(AnimationState::Idle, false)
+                == Some(AnimationState::Walk)
+        );
+        assert!(
+            machine(5.0, false).next_state(AnimationState::Walk, false)
+                == Some(AnimationState::Run)
+        );
+        assert!(machine(5.0, false)
+            .next_state(AnimationState::Run, false)
+            .is_none());
+    }
+
+    #[test]
+    fn higher_priority_wins() {
+        assert!(
+            machine(5.0, true).next_state(AnimationState::Run, false)
+                == Some(AnimationState::Attack)
+        );
+    }
+
+    #[test]
+    fn protected_states_play_through_before_lower_priorities() {
+        let machine = machine(2.0, false);
+        assert!(machine.next_state(AnimationState::Attack, false).is_none());
+        assert!(machine.next_state(AnimationState::Attack, true) == Some(AnimationState::Walk));
+    }
+
+    #[test]
+    fn high_enough_priority_interrupts() {
+        let mut machine = machine(0.0, false);
+        machine
+            .transitions
+            .push(AnimationTransition::new(AnimationState::Hurt, vec![Airborne]).with_priority(20));
+        machine.parameters.grounded = false;
+
+        assert!(machine.next_state(AnimationState::Attack, false) == Some(AnimationState::Hurt));
+    }
+
+    #[test]
+    fn getting_hurt_interrupts_attacks_and_plays_through() {
+        let mut machine = machine(0.0, false);
+        machine.parameters.hurt = true;
+        assert!(machine.next_state(AnimationState::Attack, false) == Some(AnimationState::Hurt));
+
+        machine.parameters.hurt = false;
+        machine.parameters.attacking = true;
+        assert!(machine.next_state(AnimationState::Hurt, false).is_none());
+        assert!(machine.next_state(AnimationState::Hurt, true) == Some(AnimationState::Attack));
+    }
+
+    #[test]
+    fn dying_interrupts_everything_and_stays_down() {
+        let mut machine = machine(2.0, false);
+        machine.parameters.dead = true;
+        machine.parameters.hurt = true;
+        assert!(machine.next_state(AnimationState::Hurt, false) == Some(AnimationState::Death));
+        assert!(machine.next_state(AnimationState::Death, true).is_none());
+    }
+
+    #[test]
+    fn held_attacks_play_once_and_move_on() {
+        let mut actions = ActionState::default();
+        let mut machine = machine(2.0, false);
+        let mut state = AnimationState::Walk;
+        // Pressed on the first frame and held for the rest, finishing on the fourth
+        for frame in 0..6 {
+            actions.swap();
+            actions.set(Action::Attack, 1.0);
+            machine.parameters.attacking = actions.just_pressed(Action::Attack);
+            if let Some(next) = machine.next_state(state, frame == 3) {
+                state = next;
+            }
+            if frame < 3 {
+                assert!(state == AnimationState::Attack);
+            }
+        }
+        assert!(state == AnimationState::Walk);
+    }
+
+    #[test]
+    fn sits_down_when_standing_still() {
+        let mut machine = machine(0.0, false);
+        machine.parameters.sitting = true;
+        assert!(machine.next_state(AnimationState::Idle, false) == Some(AnimationState::Sit));
+        assert!(machine.next_state(AnimationState::Sit, false).is_none());
+
+        machine.parameters.sitting = false;
+        assert!(machine.next_state(AnimationState::Sit, false) == Some(AnimationState::Idle));
+    }
+
+    #[test]
+    fn interactions_play_through() {
+        let mut machine = machine(0.0, false);
+        machine.parameters.interacting = true;
+        assert!(machine.next_state(AnimationState::Idle, false) == Some(AnimationState::Interact));
+
+        machine.parameters.interacting = false;
+        assert!(machine
+            .next_state(AnimationState::Interact, false)
+            .is_none());
+        assert!(machine.next_state(AnimationState::Interact, true) == Some(AnimationState::Idle));
+    }
+
+    #[test]
+    fn jumps_and_falls_while_airborne() {
+        let mut machine = machine(2.0, false);
+        machine.parameters.grounded = false;
+        machine.parameters.vertical_speed = 3.0;
+        assert!(machine.next_state(AnimationState::Walk, false) == Some(AnimationState::Jump));
+        assert!(machine.next_state(AnimationState::Jump, false).is_none());
+
+        machine.parameters.vertical_speed = -1.0;
+        assert!(machine.next_state(AnimationState::Jump, false) == Some(AnimationState::Fall));
+
+        machine.parameters.grounded = true;
+        assert!(machine.next_state(AnimationState::Fall, false) == Some(AnimationState::Walk));
+    }
+
+    #[test]
+    fn transitions_only_apply_from_their_state() {
+        let mut machine = machine(0.0, false);
+        machine.transitions =
+            vec![AnimationTransition::new(AnimationState::Sit, vec![]).from(AnimationState::Idle)];
+
+        assert!(machine.next_state(AnimationState::Idle, false) == Some(AnimationState::Sit));
+        assert!(machine.next_state(AnimationState::Walk, false).is_none());
+    }
+}
@@ -1,47 +1,73 @@
+use bevy::asset::Asset;
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_mod_aseprite::Aseprite;
 use std::f32::consts::PI;
+use std::time::Duration;
 
 use bevy_sprite3d::AtlasSprite3dComponent;
 
+use super::aseprite::build_animations_from_aseprite;
+use super::assets::AnimationSet;
 use super::components::Direction;
 use super::components::*;
+use super::events::*;
+use super::state_machine::AnimationStateMachine;
 
+// Which side of the character is seen, given the direction from the character towards the camera
 pub fn get_character_direction(
     animated_character: &AnimatedCharacter,
-    viewing_position: Vec3,
+    towards_camera: Vec3,
 ) -> Direction {
-    let towards_camera = animated_character.heading - viewing_position;
-    let angle = animated_character.heading.angle_between(viewing_position);
-
-    if angle < (PI / 4.0) {
-        Direction::Down
-    } else if angle > (3.0 * (PI / 4.0)) {
-        Direction::Up
-    } else {
-        let right =
-            Quat::from_euler(EulerRot::XYZ, 0.0, PI * 0.5, 0.0) * animated_character.heading;
-        let dot = towards_camera.dot(right);
-        if dot < 0.0 {
-            Direction::Left
-        } else {
-            Direction::Right
-        }
+    let heading = (animated_character.heading * Vec3::new(1.0, 0.0, 1.0)).normalize_or_zero();
+    let view = towards_camera * Vec3::new(1.0, 0.0, 1.0);
+    let right = Quat::from_euler(EulerRot::XYZ, 0.0, PI * 0.5, 0.0) * heading;
+
+    // Signed angle between the heading and the view, split into one sector per direction
+    let angle = view.dot(right).atan2(view.dot(heading));
+    let directions = animated_character.directions();
+    let sector = 2.0 * PI / directions.len() as f32;
+    let index = (angle / sector).round().rem_euclid(directions.len() as f32) as usize;
+
+    let direction = directions[index % directions.len()];
+
+    // Fall back to the closest direction that has an animation, e.g. when a diagonal is missing
+    let state = animated_character.animation_state;
+    let has_animation = |d: &Direction| animated_character.animations.contains_key(&(state, *d));
+    if has_animation(&direction) {
+        return direction;
     }
+    let eighth = PI / 4.0;
+    let distance = |i: usize| {
+        let difference = (angle - i as f32 * eighth).rem_euclid(2.0 * PI);
+        difference.min(2.0 * PI - difference)
+    };
+    Direction::EIGHT
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| has_animation(d))
+        .min_by(|(a, _), (b, _)| distance(*a).total_cmp(&distance(*b)))
+        .map(|(_, d)| *d)
+        .unwrap_or(direction)
 }
 
+// Switch to another direction, continuing from the same point in the animation.
+// Returns the sprite index to show, if there is an animation for the new direction
 pub fn set_character_direction(
-    mut animated_character: &mut AnimatedCharacter,
+    animated_character: &mut AnimatedCharacter,
     direction: Direction,
-    mut atlas_sprite: &mut AtlasSprite3dComponent,
-) {
+) -> Option<usize> {
     if animated_character.direction == direction {
-        return;
+        return None;
     }
 
     let state = animated_character.animation_state;
     let prev_direction = animated_character.direction;
     let mut current_frame_index = 0 as usize;
-    let mut new_sprite_index = atlas_sprite.index;
+    let mut prev_frame_count = 0;
+    let mut prev_reversing = false;
+    let mut prev_finished = false;
+    let mut new_sprite_index = None;
 
     // Get the previous animation
     {
@@ -51,30 +77,36 @@ pub fn set_character_direction(
 
         // Find which index in the animation we're currently on
         if prev_animation.is_some() {
-            let mut animation = prev_animation.unwrap();
+            let animation = prev_animation.unwrap();
             current_frame_index = animation.current;
+            prev_frame_count = animation.frames.len();
+            prev_reversing = animation.reversing;
+            prev_finished = animation.finished;
             // Reset the animation
-            animation.current = 0;
-            animation.timer.reset();
+            animation.restart();
         }
     }
 
-    // Get the new animation, and use the frame with the same index if possible
+    // Get the new animation, and continue from the same point in it.
+    // Frame counts can differ between directions, e.g. for diagonals
     {
         let new_animation = animated_character.animations.get_mut(&(state, direction));
         if new_animation.is_some() {
-            let mut animation = new_animation.unwrap();
-            if current_frame_index < animation.frames.len() {
-                animation.current = current_frame_index;
+            let animation = new_animation.unwrap();
+            let frame_count = animation.frames.len();
+            if prev_frame_count > 0 && current_frame_index < prev_frame_count {
+                animation.set_frame(current_frame_index * frame_count / prev_frame_count);
             } else {
-                animation.current = 0;
+                animation.set_frame(0);
             }
-            new_sprite_index = animation.frames[animation.current];
+            animation.reversing = prev_reversing;
+            animation.finished = prev_finished;
+            new_sprite_index = animation.sprite_index();
         }
     }
 
-    atlas_sprite.index = new_sprite_index;
     animated_character.direction = direction;
+    new_sprite_index
 }
 
 pub fn set_animation_state(
@@ -82,6 +114,16 @@ pub fn set_animation_state(
     animation_state: AnimationState,
 ) {
     if animated_character.animation_state == animation_state {
+        // Asking for a one-shot animation that has played through plays it again
+        let direction = animated_character.direction;
+        if let Some(animation) = animated_character
+            .animations
+            .get_mut(&(animation_state, direction))
+        {
+            if animation.finished {
+                animation.restart();
+            }
+        }
         return;
     }
 
@@ -96,8 +138,134 @@ pub fn reset_animation(animated_character: &mut AnimatedCharacter) {
     let some_animation = animated_character.animations.get_mut(&(state, direction));
     if some_animation.is_some() {
         let animation = some_animation.unwrap();
-        animation.current = 0;
-        animation.timer.reset();
+        animation.restart();
+    }
+}
+
+// Rebuild the animations of characters whose AnimationSet was just assigned or (re)loaded
+pub fn apply_animation_sets(
+    mut asset_events: EventReader<AssetEvent<AnimationSet>>,
+    animation_sets: Res<Assets<AnimationSet>>,
+    mut query: Query<(
+        Ref<Handle<AnimationSet>>,
+        &mut AnimatedCharacter,
+        &mut AtlasSprite3dComponent,
+    )>,
+) {
+    let loaded = get_loaded_handles(&mut asset_events);
+
+    for (handle, mut animated_character, mut atlas_sprite) in &mut query {
+        if !handle.is_changed() && !loaded.contains(&*handle) {
+            continue;
+        }
+        let Some(animation_set) = animation_sets.get(&*handle) else {
+            continue;
+        };
+
+        replace_animations(
+            &mut animated_character,
+            animation_set.build_animations(),
+            &mut atlas_sprite,
+        );
+    }
+}
+
+// Same as apply_animation_sets, but for characters animated straight from Aseprite tags
+pub fn apply_aseprite_animations(
+    mut asset_events: EventReader<AssetEvent<Aseprite>>,
+    aseprites: Res<Assets<Aseprite>>,
+    mut query: Query<(
+        Ref<Handle<Aseprite>>,
+        &mut AnimatedCharacter,
+        &mut AtlasSprite3dComponent,
+    )>,
+) {
+    let loaded = get_loaded_handles(&mut asset_events);
+
+    for (handle, mut animated_character, mut atlas_sprite) in &mut query {
+        if !handle.is_changed() && !loaded.contains(&*handle) {
+            continue;
+        }
+        let Some(aseprite) = aseprites.get(&*handle) else {
+            continue;
+        };
+
+        replace_animations(
+            &mut animated_character,
+            build_animations_from_aseprite(aseprite.info()),
+            &mut atlas_sprite,
+        );
+    }
+}
+
+fn get_loaded_handles<T: Asset>(
+    asset_events: &mut EventReader<AssetEvent<T>>,
+) -> HashSet<Handle<T>> {
+    let mut loaded = HashSet::new();
+    for event in asset_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                loaded.insert(handle.clone_weak());
+            }
+            AssetEvent::Removed { .. } => (),
+        }
+    }
+    loaded
+}
+
+fn replace_animations(
+    animated_character: &mut AnimatedCharacter,
+    animations: HashMap<(AnimationState, Direction), Animation>,
+    atlas_sprite: &mut AtlasSprite3dComponent,
+) {
+    animated_character.animations = animations;
+
+    // Show the first frame of the current animation straight away
+    let state = animated_character.animation_state;
+    let direction = animated_character.direction;
+    if let Some(sprite_index) = animated_character
+        .animations
+        .get(&(state, direction))
+        .and_then(Animation::sprite_index)
+    {
+        atlas_sprite.index = sprite_index;
+    }
+}
+
+// Let each state machine pick the animation for its character
+pub fn evaluate_animation_state_machines(
+    mut query: Query<(&AnimationStateMachine, &mut AnimatedCharacter)>,
+) {
+    for (state_machine, mut animated_character) in &mut query {
+        let state = animated_character.animation_state;
+        let direction = animated_character.direction;
+        // Nothing to play counts as finished, so a missing animation can't get a character stuck
+        let finished = animated_character
+            .animations
+            .get(&(state, direction))
+            .map_or(true, |animation| animation.finished);
+
+        if let Some(next_state) = state_machine.next_state(state, finished) {
+            set_animation_state(&mut animated_character, next_state);
+        }
+    }
+}
+
+// Keep track of how fast each character moves, to scale its animations with
+pub fn measure_movement_speed(
+    mut query: Query<(&mut AnimatedCharacter, &Transform)>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (mut animated_character, transform) in &mut query {
+        let position = transform.translation * Vec3::new(1.0, 0.0, 1.0);
+        if let Some(last_position) = animated_character.last_position {
+            if delta > 0.0 {
+                animated_character.movement_speed = (last_position.distance(position) / delta)
+                    .min(animated_character.max_movement_speed);
+            }
+        }
+        animated_character.last_position = Some(position);
     }
 }
 
@@ -114,8 +282,14 @@ pub fn turning_toward_camera(
             return;
         }
 
+        // Look at the same height, so billboards stay upright while jumping
+        let target = Vec3::new(
+            look_position.x,
+            obj_transform.translation.y,
+            look_position.z,
+        );
         let rotation = Transform::from_translation(obj_transform.translation)
-            .looking_at(look_position, Vec3::Y)
+            .looking_at(target, Vec3::Y)
             .rotation
             .mul_quat(Quat::from_rotation_y(PI));
         obj_transform.rotation = obj_transform
@@ -125,45 +299,142 @@ pub fn turning_toward_camera(
 }
 
 pub fn update_character_direction(
-    mut query: Query<(&mut AnimatedCharacter, &mut AtlasSprite3dComponent)>,
+    mut query: Query<(
+        &mut AnimatedCharacter,
+        &mut AtlasSprite3dComponent,
+        &Transform,
+    )>,
     camera_query: Query<&Transform, (With<Camera>, Without<TurnTowardCamera>)>,
 ) {
     let camera = camera_query.single();
     let look_position = (camera.translation - camera.forward() * 10.0) * Vec3::new(1.0, 0.0, 1.0);
 
-    for (mut animated_character, mut atlas_sprite) in &mut query {
-        let direction = get_character_direction(&animated_character, look_position);
-        if direction != animated_character.direction {
-            set_character_direction(&mut animated_character, direction, &mut atlas_sprite);
+    for (mut animated_character, mut atlas_sprite, transform) in &mut query {
+        // The billboards face the look position, so that's where they're seen from
+        let towards_camera = look_position - transform.translation;
+        let direction = get_character_direction(&animated_character, towards_camera);
+        if let Some(sprite_index) = set_character_direction(&mut animated_character, direction) {
+            atlas_sprite.index = sprite_index;
         }
     }
 }
 
+// Advance the animation timer, stepping to the next frame when it runs out.
+// Returns the sprite index to show, or None for an animation without frames
+pub fn step_animation(animation: &mut Animation, delta: Duration) -> Option<usize> {
+    if animation.frames.is_empty() {
+        return None;
+    }
+    if animation.finished {
+        return animation.sprite_index();
+    }
+
+    // Pick up any change to the animation's speed
+    let frame_duration = Duration::from_secs_f32(animation.frame_duration(animation.current));
+    if animation.timer.duration() != frame_duration {
+        animation.timer.set_duration(frame_duration);
+    }
+
+    animation.timer.tick(delta);
+    if animation.timer.just_finished() {
+        let current = animation.current;
+        let last = animation.frames.len() - 1;
+        let next = match animation.mode {
+            PlaybackMode::Loop => {
+                if current >= last {
+                    0
+                } else {
+                    current + 1
+                }
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    0
+                } else if animation.reversing {
+                    animation.reversing = current > 1;
+                    current - 1
+                } else {
+                    animation.reversing = current + 1 >= last;
+                    current + 1
+                }
+            }
+            PlaybackMode::Once => {
+                animation.finished = current >= last;
+                if animation.finished {
+                    0
+                } else {
+                    current + 1
+                }
+            }
+            PlaybackMode::ClampForever => {
+                animation.finished = current >= last;
+                if animation.finished {
+                    current
+                } else {
+                    current + 1
+                }
+            }
+        };
+        animation.set_frame(next);
+    }
+
+    animation.sprite_index()
+}
+
 pub fn animate_sprite_system(
     time: Res<Time>,
-    mut query: Query<(&mut AnimatedCharacter, &mut AtlasSprite3dComponent)>,
+    mut query: Query<(Entity, &mut AnimatedCharacter, &mut AtlasSprite3dComponent)>,
+    mut marker_events: EventWriter<AnimationMarkerReached>,
+    mut finished_events: EventWriter<AnimationFinished>,
 ) {
-    for (mut animated_character, mut atlas_sprite) in query.iter_mut() {
+    for (entity, mut animated_character, mut atlas_sprite) in query.iter_mut() {
         // Get the correct animation
         let state = animated_character.animation_state;
         let direction = animated_character.direction;
-        let some_animation = animated_character.animations.get_mut(&(state, direction));
-        if some_animation.is_none() {
+        let playback_rate = animated_character.playback_rate;
+        let movement_speed = animated_character.movement_speed;
+        let Some(animation) = animated_character.animations.get_mut(&(state, direction)) else {
+            continue;
+        };
+        let scale = animation.playback_scale(playback_rate, movement_speed);
+
+        let was_finished = animation.finished;
+        let Some(mut current_sprite_index) = step_animation(animation, time.delta().mul_f32(scale))
+        else {
             continue;
+        };
+        let just_finished = animation.finished && !was_finished;
+
+        // Let anyone interested know when a marked frame is reached
+        if animation.timer.just_finished() && !animation.finished {
+            for marker in animation.markers_on_current_frame() {
+                marker_events.send(AnimationMarkerReached {
+                    entity,
+                    state,
+                    direction,
+                    marker: marker.name.clone(),
+                });
+            }
         }
 
-        let mut animation = some_animation.unwrap();
-        let mut current_sprite_index = animation.frames[animation.current];
+        if just_finished {
+            finished_events.send(AnimationFinished {
+                entity,
+                state,
+                direction,
+            });
 
-        // Update animation timer
-        animation.timer.tick(time.delta());
-        if animation.timer.just_finished() {
-            // Step forward one frame
-            animation.current += 1;
-            if animation.current >= animation.frames.len() {
-                animation.current = 0;
+            // Move on to whatever was queued up after this animation
+            if let Some(next_state) = animated_character.queued_state.take() {
+                set_animation_state(&mut animated_character, next_state);
+                if let Some(sprite_index) = animated_character
+                    .animations
+                    .get(&(next_state, direction))
+                    .and_then(Animation::sprite_index)
+                {
+                    current_sprite_index = sprite_index;
+                }
             }
-            current_sprite_index = animation.frames[animation.current];
         }
 
         // Update the atlas sprite
@@ -172,3 +443,317 @@ pub fn animate_sprite_system(
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Same layout as Character.anim.ron
+    pub fn character_animations() -> HashMap<(AnimationState, Direction), Animation> {
+        let mut animations = HashMap::new();
+        for (i, direction) in [
+            Direction::Down,
+            Direction::Right,
+            Direction::Up,
+            Direction::Left,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            animations.insert(
+                (AnimationState::Idle, direction),
+                Animation::new(vec![i], 0.1),
+            );
+            animations.insert(
+                (AnimationState::Walk, direction),
+                Animation::new((0..8).map(|frame| frame * 4 + 4 + i).collect(), 0.1),
+            );
+        }
+        animations
+    }
+
+    fn walking_character(heading: Vec3) -> AnimatedCharacter {
+        AnimatedCharacter {
+            heading,
+            animation_state: AnimationState::Walk,
+            animations: character_animations(),
+            ..default()
+        }
+    }
+
+    fn direction_at_angle(animated_character: &AnimatedCharacter, degrees: f32) -> Direction {
+        let towards_camera = Quat::from_rotation_y(degrees.to_radians()) * Vec3::Z;
+        get_character_direction(animated_character, towards_camera)
+    }
+
+    #[test]
+    fn facing_the_camera_shows_the_front() {
+        let character = walking_character(Vec3::Z);
+        assert!(get_character_direction(&character, Vec3::Z) == Direction::Down);
+        assert!(get_character_direction(&character, Vec3::NEG_Z) == Direction::Up);
+    }
+
+    #[test]
+    fn camera_on_the_side_shows_the_profile() {
+        // Seen from +X, a character heading +Z walks towards the viewer's left
+        let character = walking_character(Vec3::Z);
+        assert!(get_character_direction(&character, Vec3::X) == Direction::Left);
+        assert!(get_character_direction(&character, Vec3::NEG_X) == Direction::Right);
+    }
+
+    #[test]
+    fn direction_follows_the_heading() {
+        let character = walking_character(Vec3::X);
+        assert!(get_character_direction(&character, Vec3::X) == Direction::Down);
+        assert!(get_character_direction(&character, Vec3::NEG_Z) == Direction::Left);
+        assert!(get_character_direction(&character, Vec3::Z) == Direction::Right);
+    }
+
+    #[test]
+    fn direction_ignores_height() {
+        let character = walking_character(Vec3::new(0.0, 0.5, 1.0));
+        let towards_camera = Vec3::new(0.0, 5.0, 1.0);
+        assert!(get_character_direction(&character, towards_camera) == Direction::Down);
+    }
+
+    #[test]
+    fn four_directions_split_at_45_degrees() {
+        let character = walking_character(Vec3::Z);
+        assert!(direction_at_angle(&character, 44.0) == Direction::Down);
+        assert!(direction_at_angle(&character, 46.0) == Direction::Left);
+        assert!(direction_at_angle(&character, 134.0) == Direction::Left);
+        assert!(direction_at_angle(&character, 136.0) == Direction::Up);
+        assert!(direction_at_angle(&character, -44.0) == Direction::Down);
+        assert!(direction_at_angle(&character, -46.0) == Direction::Right);
+        assert!(direction_at_angle(&character, -179.0) == Direction::Up);
+    }
+
+    #[test]
+    fn eight_directions_split_at_22_5_degrees() {
+        let mut character = walking_character(Vec3::Z);
+        character.eight_directions = true;
+        for direction in Direction::EIGHT {
+            character.animations.insert(
+                (AnimationState::Walk, direction),
+                Animation::new(vec![0], 0.1),
+            );
+        }
+
+        assert!(direction_at_angle(&character, 20.0) == Direction::Down);
+        assert!(direction_at_angle(&character, 25.0) == Direction::DownLeft);
+        assert!(direction_at_angle(&character, 135.0) == Direction::UpLeft);
+        assert!(direction_at_angle(&character, -45.0) == Direction::DownRight);
+        assert!(direction_at_angle(&character, -135.0) == Direction::UpRight);
+    }
+
+    #[test]
+    fn missing_diagonals_fall_back_to_the_nearest_direction() {
+        let mut character = walking_character(Vec3::Z);
+        character.eight_directions = true;
+
+        assert!(direction_at_angle(&character, 40.0) == Direction::Down);
+        assert!(direction_at_angle(&character, 50.0) == Direction::Left);
+        assert!(direction_at_angle(&character, 140.0) == Direction::Up);
+    }
+
+    #[test]
+    fn changing_direction_keeps_the_frame() {
+        let mut character = walking_character(Vec3::Z);
+        character
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap()
+            .set_frame(3);
+
+        let sprite_index = set_character_direction(&mut character, Direction::Left);
+
+        assert!(character.direction == Direction::Left);
+        assert_eq!(sprite_index, Some(3 * 4 + 4 + 3));
+        let down = &character.animations[&(AnimationState::Walk, Direction::Down)];
+        assert_eq!(down.current, 0);
+    }
+
+    #[test]
+    fn changing_direction_scales_the_frame_to_the_new_frame_count() {
+        let mut character = walking_character(Vec3::Z);
+        character.animations.insert(
+            (AnimationState::Walk, Direction::Up),
+            Animation::new(vec![100, 101, 102, 103], 0.1),
+        );
+        character
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap()
+            .set_frame(6);
+
+        let sprite_index = set_character_direction(&mut character, Direction::Up);
+
+        assert_eq!(sprite_index, Some(103));
+    }
+
+    #[test]
+    fn changing_to_the_same_direction_does_nothing() {
+        let mut character = walking_character(Vec3::Z);
+        assert_eq!(
+            set_character_direction(&mut character, Direction::Down),
+            None
+        );
+    }
+
+    #[test]
+    fn changing_to_a_direction_without_animation_still_turns() {
+        let mut character = walking_character(Vec3::Z);
+        character
+            .animations
+            .remove(&(AnimationState::Walk, Direction::Up));
+
+        assert_eq!(set_character_direction(&mut character, Direction::Up), None);
+        assert!(character.direction == Direction::Up);
+    }
+
+    #[test]
+    fn reset_animation_rewinds_the_current_animation() {
+        let mut character = walking_character(Vec3::Z);
+        let animation = character
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap();
+        animation.set_frame(5);
+        animation.timer.tick(Duration::from_secs_f32(0.05));
+
+        reset_animation(&mut character);
+
+        let animation = &character.animations[&(AnimationState::Walk, Direction::Down)];
+        assert_eq!(animation.current, 0);
+        assert_eq!(animation.timer.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn setting_a_finished_one_shot_again_replays_it() {
+        let mut character = walking_character(Vec3::Z);
+        let animation = character
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap();
+        animation.mode = PlaybackMode::ClampForever;
+        animation.set_frame(7);
+        animation.finished = true;
+
+        set_animation_state(&mut character, AnimationState::Walk);
+
+        let animation = &character.animations[&(AnimationState::Walk, Direction::Down)];
+        assert!(!animation.finished);
+        assert_eq!(animation.current, 0);
+    }
+
+    #[test]
+    fn setting_a_playing_state_again_keeps_its_frame() {
+        let mut character = walking_character(Vec3::Z);
+        character
+            .animations
+            .get_mut(&(AnimationState::Walk, Direction::Down))
+            .unwrap()
+            .set_frame(3);
+
+        set_animation_state(&mut character, AnimationState::Walk);
+
+        let animation = &character.animations[&(AnimationState::Walk, Direction::Down)];
+        assert_eq!(animation.current, 3);
+    }
+
+    #[test]
+    fn step_animation_advances_and_wraps() {
+        let mut animation = Animation::new(vec![4, 8, 12], 0.1);
+        let frame_time = Duration::from_secs_f32(0.1);
+
+        assert_eq!(
+            step_animation(&mut animation, Duration::from_secs_f32(0.05)),
+            Some(4)
+        );
+        assert_eq!(
+            step_animation(&mut animation, Duration::from_secs_f32(0.05)),
+            Some(8)
+        );
+        assert_eq!(step_animation(&mut animation, frame_time), Some(12));
+        assert_eq!(step_animation(&mut animation, frame_time), Some(4));
+    }
+
+    fn play(animation: &mut Animation, steps: usize) -> Vec<usize> {
+        let frame_time = Duration::from_secs_f32(0.25);
+        (0..steps)
+            .map(|_| step_animation(animation, frame_time).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn step_animation_ping_pongs() {
+        let mut animation = Animation {
+            mode: PlaybackMode::PingPong,
+            ..Animation::new(vec![0, 1, 2], 0.25)
+        };
+        assert_eq!(play(&mut animation, 6), vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn step_animation_once_rewinds_and_stops() {
+        let mut animation = Animation {
+            mode: PlaybackMode::Once,
+            ..Animation::new(vec![0, 1, 2], 0.25)
+        };
+        assert_eq!(play(&mut animation, 5), vec![1, 2, 0, 0, 0]);
+        assert!(animation.finished);
+    }
+
+    #[test]
+    fn step_animation_clamp_forever_holds_the_last_frame() {
+        let mut animation = Animation {
+            mode: PlaybackMode::ClampForever,
+            ..Animation::new(vec![0, 1, 2], 0.25)
+        };
+        assert_eq!(play(&mut animation, 5), vec![1, 2, 2, 2, 2]);
+        assert!(animation.finished);
+
+        animation.restart();
+        assert!(!animation.finished);
+        assert_eq!(play(&mut animation, 1), vec![1]);
+    }
+
+    #[test]
+    fn step_animation_picks_up_speed_changes() {
+        let mut animation = Animation::new(vec![0, 1, 2], 0.25);
+        animation.speed = 0.5;
+        let frame_time = Duration::from_secs_f32(0.25);
+
+        assert_eq!(step_animation(&mut animation, frame_time), Some(0));
+        assert_eq!(step_animation(&mut animation, frame_time), Some(1));
+    }
+
+    #[test]
+    fn step_animation_shows_nothing_without_frames() {
+        let mut animation = Animation::new(Vec::new(), 0.1);
+        assert_eq!(
+            step_animation(&mut animation, Duration::from_secs_f32(0.25)),
+            None
+        );
+    }
+
+    #[test]
+    fn playback_scales_with_movement_speed() {
+        let mut animation = Animation::new(vec![0], 0.1);
+        assert_eq!(animation.playback_scale(1.0, 5.0), 1.0);
+
+        animation.reference_speed = Some(2.0);
+        assert_eq!(animation.playback_scale(1.0, 5.0), 2.5);
+        assert_eq!(animation.playback_scale(0.5, 5.0), 1.25);
+    }
+
+    #[test]
+    fn step_animation_uses_per_frame_durations() {
+        let mut animation = Animation::with_frame_durations(vec![0, 1], vec![0.25, 0.5]);
+        let frame_time = Duration::from_secs_f32(0.25);
+
+        assert_eq!(step_animation(&mut animation, frame_time), Some(1));
+        assert_eq!(step_animation(&mut animation, frame_time), Some(1));
+        assert_eq!(step_animation(&mut animation, frame_time), Some(0));
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// A scripted camera move through keyframes, played by CameraMode::Cinematic
+#[derive(Clone, PartialEq, Debug, Default, Deserialize)]
+pub struct CinematicPath {
+    // In order of time
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub struct CameraKeyframe {
+    // Seconds from the start of the path
+    pub time: f32,
+    pub position: Vec3,
+    pub look_at: Vec3,
+}
+
+impl CinematicPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // Where the camera is at `time`, curving smoothly through the keyframes.
+    // Holds still on the first and last keyframe before and after the path
+    pub fn sample(&self, time: f32) -> Option<Transform> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+        let next = keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(last + 1);
+        if next == 0 || next > last {
+            let keyframe = keyframes[next.min(last)];
+            return Some(
+                Transform::from_translation(keyframe.position)
+                    .looking_at(keyframe.look_at, Vec3::Y),
+            );
+        }
+
+        let (from, to) = (keyframes[next - 1], keyframes[next]);
+        let before = keyframes[(next - 1).saturating_sub(1)];
+        let after = keyframes[(next + 1).min(last)];
+        let t = (time - from.time) / (to.time - from.time);
+        let position = catmull_rom(
+            before.position,
+            from.position,
+            to.position,
+            after.position,
+            t,
+        );
+        let look_at = catmull_rom(before.look_at, from.look_at, to.look_at, after.look_at, t);
+        Some(Transform::from_translation(position).looking_at(look_at, Vec3::Y))
+    }
+}
+
+// Passes through `p1` at 0 and `p2` at 1, heading from `p0` and on to `p3`
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> CinematicPath {
+        CinematicPath {
+            keyframes: vec![
+                CameraKeyframe {
+                    time: 0.0,
+                    position: Vec3::new(0.0, 2.0, 5.0),
+                    look_at: Vec3::ZERO,
+                },
+                CameraKeyframe {
+                    time: 2.0,
+                    position: Vec3::new(5.0, 2.0, 0.0),
+                    look_at: Vec3::ZERO,
+                },
+                CameraKeyframe {
+                    time: 3.0,
+                    position: Vec3::new(0.0, 4.0, -5.0),
+                    look_at: Vec3::Y,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn passes_through_every_keyframe() {
+        let path = path();
+        assert_eq!(path.duration(), 3.0);
+        for keyframe in path.keyframes.iter() {
+            let transform = path.sample(keyframe.time).unwrap();
+            assert!(transform.translation.abs_diff_eq(keyframe.position, 1e-5));
+        }
+    }
+
+    #[test]
+    fn moves_smoothly_between_keyframes() {
+        let path = path();
+        let mut previous = path.sample(0.0).unwrap().translation;
+        for step in 1..=300 {
+            let position = path.sample(step as f32 * 0.01).unwrap().translation;
+            assert!(position.distance(previous) < 0.1);
+            previous = position;
+        }
+    }
+
+    #[test]
+    fn holds_still_outside_the_path() {
+        let path = path();
+        assert_eq!(path.sample(-1.0), path.sample(0.0));
+        assert_eq!(path.sample(10.0), path.sample(3.0));
+        assert!(CinematicPath::default().sample(0.0).is_none());
+    }
+}
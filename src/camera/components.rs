@@ -1,21 +1,76 @@
-use std::f32::{INFINITY, NEG_INFINITY};
+use std::f32::consts::PI;
 
 use bevy::prelude::*;
 
+use super::cinematic::CinematicPath;
+use super::smoothing::FollowSmoothing;
+
+// How the camera is driven. Switching modes blends smoothly from where the camera was
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub enum CameraMode {
+    // Orbit around the player, see FollowCamera
+    #[default]
+    Follow,
+    // Fly around freely with the movement keys, for debugging
+    FreeFly,
+    // Look at the player from a set angle that turns in steps, see FixedCamera
+    Fixed,
+    // Play a scripted path, then go back to the mode from before
+    Cinematic(CinematicPath),
+}
+
+impl CameraMode {
+    // The mode after this one, when cycling through them
+    pub fn next(&self) -> CameraMode {
+        match self {
+            CameraMode::Follow => CameraMode::Fixed,
+            CameraMode::Fixed => CameraMode::FreeFly,
+            CameraMode::FreeFly | CameraMode::Cinematic(_) => CameraMode::Follow,
+        }
+    }
+}
+
 #[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct FollowCamera {
     // Offset to the center point of the target
     pub offset: Vec3,
     // Distance towards target
     pub zoom: f32,
     pub zoom_speed: f32,
+    // Zooming by holding a trigger, in metres per second
+    pub zoom_axis_speed: f32,
     pub zoom_limit_min: f32,
     pub zoom_limit_max: f32,
-    // Used to smooth the movement and rotation
-    pub speed_transition: f32,
+    // How quickly the camera catches up with the target, with its orbit and with its zoom
+    pub position_stiffness: f32,
+    pub rotation_stiffness: f32,
+    pub zoom_stiffness: f32,
+    // Where the camera is while catching up, None until it first gets there
+    #[reflect(ignore)]
+    pub smoothing: Option<FollowSmoothing>,
+    // Half the size of the rectangle on the ground, across and along the view, that the
+    // target can move around in without moving the camera
+    pub dead_zone: Vec2,
+    #[reflect(ignore)]
+    pub dead_zone_center: Option<Vec3>,
+    // Look ahead of a moving target by where it will be in this many seconds,
+    // up to `look_ahead_max` metres
+    pub look_ahead: f32,
+    pub look_ahead_max: f32,
+    // Interactable characters this close to the target are kept in view too, zooming out
+    // if needed, with `framing_margin` metres of space around them
+    pub framing_radius: f32,
+    pub framing_margin: f32,
+    // Looking at a character picks the closest interactable one this close to the player,
+    // blending over to it and back in `look_at_transition` seconds
+    pub look_at_distance: f32,
+    pub look_at_transition: f32,
     // Used to rotate around the followed object
     pub rotation_horizontal: f32,
     pub rotation_horizontal_speed: f32,
+    // Orbiting with a stick, in radians per second
+    pub rotation_axis_speed: f32,
     pub rotation_horizontal_limit_min: f32,
     pub rotation_horizontal_limit_max: f32,
     // Used to rotate up and down around the followed object
@@ -23,6 +78,17 @@ pub struct FollowCamera {
     pub rotation_vertical_speed: f32,
     pub rotation_vertical_limit_min: f32,
     pub rotation_vertical_limit_max: f32,
+    // Size of the sphere kept clear of walls between the target and the camera
+    pub collision_radius: f32,
+    // How quickly the camera moves back out once no longer blocked
+    pub collision_recovery_speed: f32,
+    // How far walls allow the camera from the target, pulled in straight away and eased back out
+    #[reflect(ignore)]
+    pub collision_distance: f32,
+    // Opacity of meshes between the camera and the target
+    pub occluded_alpha: f32,
+    // How quickly meshes fade out and back in, in opacity per second
+    pub occlusion_fade_speed: f32,
 }
 
 impl Default for FollowCamera {
@@ -31,17 +97,153 @@ impl Default for FollowCamera {
             offset: Vec3::ZERO,
             zoom: 8.0,
             zoom_speed: 30.0,
+            zoom_axis_speed: 8.0,
             zoom_limit_min: 2.0,
             zoom_limit_max: 18.0,
-            speed_transition: 10.0,
+            position_stiffness: 10.0,
+            rotation_stiffness: 12.0,
+            zoom_stiffness: 8.0,
+            smoothing: None,
+            dead_zone: Vec2::new(0.5, 0.3),
+            dead_zone_center: None,
+            look_ahead: 0.3,
+            look_ahead_max: 1.5,
+            framing_radius: 3.0,
+            framing_margin: 1.0,
+            look_at_distance: 4.0,
+            look_at_transition: 0.6,
             rotation_horizontal: 0.2,
             rotation_horizontal_speed: 0.5,
-            rotation_horizontal_limit_min: NEG_INFINITY,
-            rotation_horizontal_limit_max: INFINITY,
+            rotation_axis_speed: 2.5,
+            // Unlimited, but finite so they can be written to a save file
+            rotation_horizontal_limit_min: f32::MIN,
+            rotation_horizontal_limit_max: f32::MAX,
             rotation_vertical: -0.6,
             rotation_vertical_speed: 0.3,
             rotation_vertical_limit_min: -1.2,
             rotation_vertical_limit_max: -0.05,
+            collision_radius: 0.2,
+            collision_recovery_speed: 3.0,
+            collision_distance: f32::MAX,
+            occluded_alpha: 0.3,
+            occlusion_fade_speed: 4.0,
+        }
+    }
+}
+
+// A mesh faded out because it is between the camera and the target. It shows a copy of
+// its material, and gets the original back once fully opaque again
+#[derive(Component)]
+pub struct OcclusionFade {
+    pub alpha: f32,
+    pub original: Handle<StandardMaterial>,
+}
+
+// Settings for CameraMode::FreeFly
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct FreeFlyCamera {
+    // In metres per second
+    pub speed: f32,
+    pub sprint_speed: f32,
+    // Looking around with the mouse, in radians per pixel moved
+    pub mouse_look_speed: f32,
+    // Looking around with a stick, in radians per second
+    pub stick_look_speed: f32,
+    // Where the camera is flying, picked up from the camera when switching to this mode
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for FreeFlyCamera {
+    fn default() -> Self {
+        Self {
+            speed: 4.0,
+            sprint_speed: 12.0,
+            mouse_look_speed: 0.005,
+            stick_look_speed: 2.0,
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
         }
     }
 }
+
+// Settings for CameraMode::Fixed. The default is an isometric view
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct FixedCamera {
+    // Offset to the center point of the target
+    pub offset: Vec3,
+    pub distance: f32,
+    // Angle looking down, negative
+    pub pitch: f32,
+    // Angle around the target the camera is turning towards
+    pub yaw: f32,
+    // How far one press turns the camera
+    pub snap_angle: f32,
+    // How quickly the camera turns to a new angle
+    pub turn_speed: f32,
+    // The angle the camera is at right now, turning towards `yaw`
+    #[reflect(ignore)]
+    pub current_yaw: f32,
+}
+
+impl Default for FixedCamera {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::new(0.0, 0.5, 0.0),
+            distance: 10.0,
+            // Looking down at the angle of a cube's diagonal
+            pitch: -(0.5_f32.sqrt()).atan(),
+            yaw: PI / 4.0,
+            snap_angle: PI / 2.0,
+            turn_speed: 10.0,
+            current_yaw: PI / 4.0,
+        }
+    }
+}
+
+// Playback of CameraMode::Cinematic
+#[derive(Component, Default)]
+pub struct CinematicCamera {
+    pub time: f32,
+    // The mode to go back to once the path is done
+    pub return_to: CameraMode,
+}
+
+// The entity the follow and fixed angle cameras look at. Without one they hold still
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CameraTarget(pub Entity);
+
+// Eases the camera from where it was to where the current mode puts it, after switching
+// modes or targets
+#[derive(Component)]
+pub struct CameraBlend {
+    // How long switching modes takes, in seconds
+    pub duration: f32,
+    pub from: Option<Transform>,
+    pub elapsed: f32,
+    // How long the blend in progress takes
+    pub current_duration: f32,
+}
+
+impl Default for CameraBlend {
+    fn default() -> Self {
+        Self {
+            duration: 0.75,
+            from: None,
+            elapsed: 0.0,
+            current_duration: 0.75,
+        }
+    }
+}
+
+impl CameraBlend {
+    pub fn start(&mut self, from: Transform, duration: f32) {
+        self.from = Some(from);
+        self.elapsed = 0.0;
+        self.current_duration = duration;
+    }
+}
@@ -0,0 +1,9 @@
+use bevy::prelude::*;
+
+// Send this to point the camera at another entity, like a character the player is
+// talking to. None goes back to the player
+pub struct RetargetCamera {
+    pub target: Option<Entity>,
+    // Seconds to blend over from the old target
+    pub transition: f32,
+}
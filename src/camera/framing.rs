@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+// Move the dead zone along with the target only once the target pushes against its edge.
+// The zone is a rectangle on the ground, `half_size` across and along the view, turned
+// with the camera's `yaw`. Heights aren't held back
+pub fn follow_dead_zone(center: Vec3, target: Vec3, half_size: Vec2, yaw: f32) -> Vec3 {
+    let turn = Quat::from_rotation_y(yaw);
+    let offset = turn.inverse() * (target - center);
+    let pushed = Vec3::new(
+        offset.x - offset.x.clamp(-half_size.x, half_size.x),
+        offset.y,
+        offset.z - offset.z.clamp(-half_size.y, half_size.y),
+    );
+    center + turn * pushed
+}
+
+// How far ahead of a moving target to look, flat on the ground
+pub fn look_ahead(velocity: Vec3, seconds: f32, max_distance: f32) -> Vec3 {
+    (velocity * Vec3::new(1.0, 0.0, 1.0) * seconds).clamp_length_max(max_distance)
+}
+
+// The middle of the points, and how far back a camera with the vertical field of view
+// `fov` has to be to keep them all in view with `margin` to spare
+pub fn frame_points(points: &[Vec3], margin: f32, fov: f32) -> Option<(Vec3, f32)> {
+    let first = *points.first()?;
+    let (min, max) = points.iter().fold((first, first), |(min, max), &point| {
+        (min.min(point), max.max(point))
+    });
+    let center = (min + max) * 0.5;
+    let radius = points
+        .iter()
+        .map(|point| point.distance(center))
+        .fold(0.0, f32::max)
+        + margin;
+    Some((center, radius / (fov * 0.5).sin()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use super::*;
+
+    #[test]
+    fn dead_zone_holds_still_until_pushed() {
+        let half_size = Vec2::new(1.0, 0.5);
+        let center = follow_dead_zone(Vec3::ZERO, Vec3::new(0.8, 0.0, -0.4), half_size, 0.0);
+        assert_eq!(center, Vec3::ZERO);
+
+        let center = follow_dead_zone(Vec3::ZERO, Vec3::new(1.5, 0.0, -2.0), half_size, 0.0);
+        assert!(center.abs_diff_eq(Vec3::new(0.5, 0.0, -1.5), 1e-5));
+
+        // Heights are followed straight away
+        let center = follow_dead_zone(Vec3::ZERO, Vec3::Y, half_size, 0.0);
+        assert_eq!(center, Vec3::Y);
+    }
+
+    #[test]
+    fn dead_zone_turns_with_the_camera() {
+        // A quarter turn swaps which way is across the view
+        let half_size = Vec2::new(1.0, 0.5);
+        let center = follow_dead_zone(Vec3::ZERO, Vec3::new(0.8, 0.0, 0.0), half_size, FRAC_PI_2);
+        assert!(center.abs_diff_eq(Vec3::new(0.3, 0.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn looks_ahead_along_the_ground_up_to_a_limit() {
+        assert!(look_ahead(Vec3::new(2.0, 5.0, 0.0), 0.5, 3.0).abs_diff_eq(Vec3::X, 1e-5));
+        assert!((look_ahead(Vec3::new(10.0, 0.0, 0.0), 0.5, 3.0).length() - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn framing_backs_off_to_fit_everyone() {
+        assert!(frame_points(&[], 1.0, FRAC_PI_4).is_none());
+
+        let (center, alone) = frame_points(&[Vec3::ZERO], 1.0, FRAC_PI_4).unwrap();
+        assert_eq!(center, Vec3::ZERO);
+
+        let (center, together) =
+            frame_points(&[Vec3::ZERO, Vec3::new(4.0, 0.0, 0.0)], 1.0, FRAC_PI_4).unwrap();
+        assert!(center.abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 1e-5));
+        assert!((together / alone - 3.0).abs() < 1e-4);
+    }
+}
@@ -1,12 +1,18 @@
 use bevy::prelude::*;
 use bevy_atmosphere::prelude::*;
 
-mod components;
+pub mod cinematic;
+pub mod components;
+pub mod events;
+pub mod framing;
+pub mod smoothing;
 mod systems;
 
 use components::*;
+use events::*;
 use systems::*;
 
+use crate::physics::PhysicsSet;
 use crate::GameState;
 
 pub struct CameraPlugin;
@@ -14,8 +20,252 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<FollowCamera>()
+            .register_type::<FreeFlyCamera>()
+            .register_type::<FixedCamera>()
+            .init_resource::<CameraMode>()
+            .add_event::<RetargetCamera>()
             .add_plugin(AtmospherePlugin)
             .add_startup_system(spawn_camera)
-            .add_systems((camera_follow, camera_control).in_set(OnUpdate(GameState::Playing)));
+            // Each mode places the camera, then switching modes blends over from the last one
+            .add_systems(
+                (
+                    switch_camera_mode,
+                    look_at_character,
+                    retarget_camera,
+                    apply_system_buffers,
+                    update_camera_target,
+                    apply_system_buffers,
+                    camera_control.run_if(following),
+                    camera_follow.run_if(following),
+                    fly_camera.run_if(flying),
+                    fixed_camera.run_if(fixed),
+                    play_cinematic.run_if(playing_cinematic),
+                    blend_camera,
+                    fade_occluders,
+                )
+                    .chain()
+                    .after(PhysicsSet)
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cinematic::{CameraKeyframe, CinematicPath};
+    use super::smoothing::FollowSmoothing;
+    use super::*;
+    use crate::character::components::Player;
+    use crate::input::actions::ActionState;
+    use crate::testing::{advance, headless_app};
+
+    // The mode switching and cinematic systems
+    fn test_app() -> App {
+        let mut app = headless_app();
+        app.init_resource::<ActionState>()
+            .init_resource::<CameraMode>()
+            .add_systems(
+                (
+                    switch_camera_mode,
+                    play_cinematic.run_if(playing_cinematic),
+                    blend_camera,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    // The follow camera and the systems choosing what it looks at
+    fn target_test_app() -> App {
+        let mut app = headless_app();
+        app.add_event::<RetargetCamera>().add_systems(
+            (
+                retarget_camera,
+                apply_system_buffers,
+                update_camera_target,
+                apply_system_buffers,
+                camera_follow,
+                blend_camera,
+            )
+                .chain(),
+        );
+        app
+    }
+
+    fn spawn_follow_camera(app: &mut App) -> Entity {
+        app.world
+            .spawn((
+                Camera::default(),
+                Transform::from_xyz(0.0, 5.0, 10.0),
+                FollowCamera::default(),
+                CameraBlend::default(),
+            ))
+            .id()
+    }
+
+    fn keyframe(time: f32, position: Vec3) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position,
+            look_at: Vec3::ZERO,
+        }
+    }
+
+    #[test]
+    fn cinematics_blend_in_and_hand_back_to_the_previous_mode() {
+        let mut app = test_app();
+        let camera = app
+            .world
+            .spawn((
+                Transform::from_xyz(0.0, 0.0, 10.0),
+                FreeFlyCamera::default(),
+                CinematicCamera::default(),
+                CameraBlend {
+                    duration: 0.5,
+                    ..default()
+                },
+            ))
+            .id();
+        *app.world.resource_mut::<CameraMode>() = CameraMode::Fixed;
+        advance(&mut app, 0.0);
+
+        let path = CinematicPath {
+            keyframes: vec![
+                keyframe(0.0, Vec3::new(10.0, 0.0, 0.0)),
+                keyframe(2.0, Vec3::new(0.0, 0.0, -10.0)),
+            ],
+        };
+        *app.world.resource_mut::<CameraMode>() = CameraMode::Cinematic(path.clone());
+        advance(&mut app, 0.0);
+        // Still where it was, blending over to the path
+        let position = app.world.get::<Transform>(camera).unwrap().translation;
+        assert!(position.abs_diff_eq(Vec3::new(0.0, 0.0, 10.0), 1e-4));
+
+        advance(&mut app, 0.25);
+        let position = app.world.get::<Transform>(camera).unwrap().translation;
+        let on_path = path.sample(0.25).unwrap().translation;
+        assert!(position.distance(on_path) > 0.1 && position.x > 0.0);
+
+        advance(&mut app, 0.5);
+        let position = app.world.get::<Transform>(camera).unwrap().translation;
+        assert!(position.abs_diff_eq(path.sample(0.75).unwrap().translation, 1e-4));
+
+        for _ in 0..10 {
+            advance(&mut app, 0.25);
+        }
+        assert!(*app.world.resource::<CameraMode>() == CameraMode::Fixed);
+    }
+
+    #[test]
+    fn following_again_starts_over_from_the_current_position() {
+        let mut app = test_app();
+        let camera = app
+            .world
+            .spawn((
+                Transform::default(),
+                FreeFlyCamera::default(),
+                CinematicCamera::default(),
+                CameraBlend::default(),
+                FollowCamera::default(),
+            ))
+            .id();
+        advance(&mut app, 0.0);
+        {
+            let mut follow_camera = app.world.get_mut::<FollowCamera>(camera).unwrap();
+            follow_camera.smoothing = Some(FollowSmoothing::new(&follow_camera, Vec3::ZERO));
+        }
+
+        *app.world.resource_mut::<CameraMode>() = CameraMode::Fixed;
+        advance(&mut app, 0.1);
+        *app.world.resource_mut::<CameraMode>() = CameraMode::Follow;
+        advance(&mut app, 0.1);
+        assert!(app
+            .world
+            .get::<FollowCamera>(camera)
+            .unwrap()
+            .smoothing
+            .is_none());
+    }
+
+    #[test]
+    fn retargeting_blends_over_and_falls_back_to_the_player() {
+        let mut app = target_test_app();
+        let camera = spawn_follow_camera(&mut app);
+        let player = app.world.spawn((Player, Transform::default())).id();
+        let villager = app.world.spawn(Transform::from_xyz(20.0, 0.0, 0.0)).id();
+        advance(&mut app, 0.1);
+        assert_eq!(
+            app.world.get::<CameraTarget>(camera),
+            Some(&CameraTarget(player))
+        );
+
+        app.world.send_event(RetargetCamera {
+            target: Some(villager),
+            transition: 0.4,
+        });
+        advance(&mut app, 0.1);
+        assert_eq!(
+            app.world.get::<CameraTarget>(camera),
+            Some(&CameraTarget(villager))
+        );
+        let blend = app.world.get::<CameraBlend>(camera).unwrap();
+        assert!(blend.from.is_some());
+        assert_eq!(blend.current_duration, 0.4);
+
+        // Losing the villager goes back to the player without a hitch
+        app.world.despawn(villager);
+        advance(&mut app, 0.1);
+        assert_eq!(
+            app.world.get::<CameraTarget>(camera),
+            Some(&CameraTarget(player))
+        );
+    }
+
+    #[test]
+    fn retargets_take_effect_in_the_same_frame() {
+        let mut app = target_test_app();
+        let camera = spawn_follow_camera(&mut app);
+        let player = app.world.spawn((Player, Transform::default())).id();
+        let villager = app.world.spawn(Transform::from_xyz(20.0, 0.0, 0.0)).id();
+        advance(&mut app, 0.1);
+        advance(&mut app, 0.1);
+        app.world.send_event(RetargetCamera {
+            target: Some(villager),
+            transition: 0.0,
+        });
+        advance(&mut app, 0.1);
+        // Cut straight to the villager, without springing over from the player
+        let follow_camera = app.world.get::<FollowCamera>(camera).unwrap();
+        let focus = follow_camera.smoothing.unwrap().focus;
+        assert!(focus.abs_diff_eq(Vec3::new(20.0, 0.0, 0.0) + follow_camera.offset, 1e-4));
+
+        app.world.send_event(RetargetCamera {
+            target: None,
+            transition: 0.4,
+        });
+        advance(&mut app, 0.1);
+        assert_eq!(
+            app.world.get::<CameraTarget>(camera),
+            Some(&CameraTarget(player))
+        );
+    }
+
+    #[test]
+    fn camera_holds_still_without_a_target() {
+        let mut app = target_test_app();
+        let camera = spawn_follow_camera(&mut app);
+        let villager = app.world.spawn(Transform::from_xyz(20.0, 0.0, 0.0)).id();
+        app.world.send_event(RetargetCamera {
+            target: Some(villager),
+            transition: 0.0,
+        });
+        advance(&mut app, 0.1);
+        let following = *app.world.get::<Transform>(camera).unwrap();
+
+        app.world.despawn(villager);
+        advance(&mut app, 0.1);
+        advance(&mut app, 0.1);
+        assert!(app.world.get::<CameraTarget>(camera).is_none());
+        assert_eq!(*app.world.get::<Transform>(camera).unwrap(), following);
     }
 }
@@ -0,0 +1,133 @@
+use std::ops::{Add, Mul, Sub};
+
+use bevy::prelude::*;
+
+use super::components::FollowCamera;
+
+// Move `value` towards `target` like a critically damped spring, as fast as possible
+// without overshooting. `stiffness` is how quickly, roughly the inverse of the time
+// it takes to get most of the way there. This is the exact solution rather than a
+// step of it, so splitting the same time into more frames gives the same result
+pub fn critically_damped<T>(value: T, velocity: T, target: T, stiffness: f32, delta: f32) -> (T, T)
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let offset = value - target;
+    let decay = (-stiffness * delta).exp();
+    let slope = velocity + offset * stiffness;
+    (
+        target + (offset + slope * delta) * decay,
+        (velocity - slope * (stiffness * delta)) * decay,
+    )
+}
+
+// Where the follow camera is right now, springing towards where it should be
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FollowSmoothing {
+    pub focus: Vec3,
+    pub focus_velocity: Vec3,
+    pub rotation_horizontal: f32,
+    pub rotation_horizontal_velocity: f32,
+    pub rotation_vertical: f32,
+    pub rotation_vertical_velocity: f32,
+    pub zoom: f32,
+    pub zoom_velocity: f32,
+}
+
+impl FollowSmoothing {
+    // At rest, already where the camera should be
+    pub fn new(follow_camera: &FollowCamera, focus: Vec3) -> Self {
+        Self {
+            focus,
+            focus_velocity: Vec3::ZERO,
+            rotation_horizontal: follow_camera.rotation_horizontal,
+            rotation_horizontal_velocity: 0.0,
+            rotation_vertical: follow_camera.rotation_vertical,
+            rotation_vertical_velocity: 0.0,
+            zoom: follow_camera.zoom,
+            zoom_velocity: 0.0,
+        }
+    }
+
+    // Spring towards looking at `focus` from `zoom` away, around the camera's orbit
+    pub fn step(&mut self, follow_camera: &FollowCamera, focus: Vec3, zoom: f32, delta: f32) {
+        (self.focus, self.focus_velocity) = critically_damped(
+            self.focus,
+            self.focus_velocity,
+            focus,
+            follow_camera.position_stiffness,
+            delta,
+        );
+        (self.rotation_horizontal, self.rotation_horizontal_velocity) = critically_damped(
+            self.rotation_horizontal,
+            self.rotation_horizontal_velocity,
+            follow_camera.rotation_horizontal,
+            follow_camera.rotation_stiffness,
+            delta,
+        );
+        (self.rotation_vertical, self.rotation_vertical_velocity) = critically_damped(
+            self.rotation_vertical,
+            self.rotation_vertical_velocity,
+            follow_camera.rotation_vertical,
+            follow_camera.rotation_stiffness,
+            delta,
+        );
+        (self.zoom, self.zoom_velocity) = critically_damped(
+            self.zoom,
+            self.zoom_velocity,
+            zoom,
+            follow_camera.zoom_stiffness,
+            delta,
+        );
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, self.rotation_horizontal)
+            * Quat::from_axis_angle(Vec3::X, self.rotation_vertical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn springs_settle_without_overshooting() {
+        let (mut value, mut velocity) = (0.0, 0.0);
+        let mut previous = value;
+        for _ in 0..120 {
+            (value, velocity) = critically_damped(value, velocity, 1.0, 10.0, 1.0 / 60.0);
+            assert!(value >= previous && value <= 1.0);
+            previous = value;
+        }
+        assert!((value - 1.0_f32).abs() < 1e-3);
+    }
+
+    #[test]
+    fn same_result_at_any_frame_rate() {
+        let run = |fps: u32| {
+            let mut follow_camera = FollowCamera::default();
+            let mut smoothing = FollowSmoothing::new(&follow_camera, Vec3::ZERO);
+            // The player moves away while the camera is orbited and zoomed out
+            follow_camera.rotation_horizontal += 1.0;
+            follow_camera.rotation_vertical -= 0.3;
+            follow_camera.zoom = 12.0;
+            let focus = Vec3::new(3.0, 0.5, -2.0);
+            // Half a second, not settled yet
+            for _ in 0..fps / 2 {
+                smoothing.step(&follow_camera, focus, follow_camera.zoom, 1.0 / fps as f32);
+            }
+            smoothing
+        };
+
+        let reference = run(30);
+        assert!(reference.focus.distance(Vec3::new(3.0, 0.5, -2.0)) > 0.01);
+        for fps in [60, 144] {
+            let smoothing = run(fps);
+            assert!(smoothing.focus.abs_diff_eq(reference.focus, 1e-4));
+            assert!((smoothing.rotation_horizontal - reference.rotation_horizontal).abs() < 1e-4);
+            assert!((smoothing.rotation_vertical - reference.rotation_vertical).abs() < 1e-4);
+            assert!((smoothing.zoom - reference.zoom).abs() < 1e-4);
+        }
+    }
+}
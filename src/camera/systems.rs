@@ -1,9 +1,16 @@
-use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 use bevy_atmosphere::prelude::*;
 
 use super::components::*;
-use crate::character::components::Player;
+use super::events::RetargetCamera;
+use super::framing::{follow_dead_zone, frame_points, look_ahead};
+use super::smoothing::FollowSmoothing;
+use crate::character::components::{Interactable, Player, Velocity};
+use crate::input::actions::{Action, ActionState};
+use crate::physics::collision::{cast_sphere, ray_hit, Obstacle};
+use crate::physics::components::BoxCollider;
+use crate::save::components::Persistent;
 
 pub fn spawn_camera(mut commands: Commands) {
     let camera_transform = Transform::from_xyz(2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y);
@@ -18,67 +25,335 @@ pub fn spawn_camera(mut commands: Commands) {
             ..default()
         })
         .insert(AtmosphereCamera::default())
-        .insert(Name::new("Follow Camera"));
+        .insert(FreeFlyCamera::default())
+        .insert(FixedCamera::default())
+        .insert(CinematicCamera::default())
+        .insert(CameraBlend::default())
+        .insert(Name::new("Follow Camera"))
+        .insert(Persistent);
+}
+
+// Follow the player when there is nothing else to follow, and let go of targets that are gone
+pub fn update_camera_target(
+    mut commands: Commands,
+    camera_query: Query<(Entity, Option<&CameraTarget>), With<FollowCamera>>,
+    targets: Query<(), (With<Transform>, Without<Camera>)>,
+    player_query: Query<Entity, With<Player>>,
+) {
+    for (camera, target) in &camera_query {
+        if let Some(&CameraTarget(target)) = target {
+            if targets.contains(target) {
+                continue;
+            }
+            info!(
+                "Camera target {:?} is gone, going back to the player",
+                target
+            );
+        }
+        // The player is spawned once its prefab has loaded, until then the camera holds still
+        match player_query.iter().next() {
+            Some(player) => {
+                commands.entity(camera).insert(CameraTarget(player));
+            }
+            None if target.is_some() => {
+                commands.entity(camera).remove::<CameraTarget>();
+            }
+            None => (),
+        }
+    }
+}
+
+// Point the camera at another target, blending over from where it is
+pub fn retarget_camera(
+    mut commands: Commands,
+    mut retarget_events: EventReader<RetargetCamera>,
+    mut camera_query: Query<(Entity, &Transform, &mut FollowCamera, &mut CameraBlend)>,
+) {
+    let Some(event) = retarget_events.iter().last() else {
+        return;
+    };
+    for (camera, transform, mut follow_camera, mut blend) in &mut camera_query {
+        match event.target {
+            Some(target) => {
+                commands.entity(camera).insert(CameraTarget(target));
+            }
+            None => {
+                commands.entity(camera).remove::<CameraTarget>();
+            }
+        }
+        // Start following the new target straight away, rather than from the old dead zone
+        follow_camera.dead_zone_center = None;
+        // With nothing to blend over, don't spring across from the old target either
+        if event.transition <= 0.0 {
+            follow_camera.smoothing = None;
+        }
+        blend.start(*transform, event.transition);
+    }
+}
+
+// Look at the closest interactable character, or back at the player if already looking at one
+pub fn look_at_character(
+    actions: Res<ActionState>,
+    camera_query: Query<(&CameraTarget, &FollowCamera)>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    interactables: Query<(Entity, &Transform), With<Interactable>>,
+    mut retarget_events: EventWriter<RetargetCamera>,
+) {
+    if !actions.just_pressed(Action::LookAtCharacter) {
+        return;
+    }
+    let (Ok((&CameraTarget(target), follow_camera)), Ok((player, player_transform))) =
+        (camera_query.get_single(), player_query.get_single())
+    else {
+        return;
+    };
+
+    if target != player {
+        retarget_events.send(RetargetCamera {
+            target: None,
+            transition: follow_camera.look_at_transition,
+        });
+        return;
+    }
+    let closest = interactables
+        .iter()
+        .map(|(entity, transform)| {
+            (
+                entity,
+                transform.translation.distance(player_transform.translation),
+            )
+        })
+        .filter(|&(_, distance)| distance <= follow_camera.look_at_distance)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((character, _)) = closest {
+        retarget_events.send(RetargetCamera {
+            target: Some(character),
+            transition: follow_camera.look_at_transition,
+        });
+    }
 }
 
 pub fn camera_follow(
-    mut camera_query: Query<(&mut Transform, &FollowCamera), (With<FollowCamera>, Without<Player>)>,
-    player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
+    mut camera_query: Query<
+        (
+            &mut Transform,
+            &mut FollowCamera,
+            Option<&CameraTarget>,
+            Option<&Projection>,
+        ),
+        With<Camera>,
+    >,
+    targets: Query<(&Transform, Option<&Velocity>), Without<Camera>>,
+    interactables: Query<(Entity, &Transform), (With<Interactable>, Without<Camera>)>,
+    colliders: Query<(&BoxCollider, &Transform), Without<Camera>>,
     time: Res<Time>,
 ) {
-    let player_transform = player_query.single();
-    let (mut camera_transform, follow_camera) = camera_query.single_mut();
-
-    let rot_hor = Quat::from_axis_angle(Vec3::Y, follow_camera.rotation_horizontal);
-    let rot_ver = Quat::from_axis_angle(Vec3::X, follow_camera.rotation_vertical);
-    let target_rotation = rot_hor * rot_ver;
-    let target_position = target_rotation.mul_vec3(Vec3::Z * follow_camera.zoom)
-        + player_transform.translation
-        + follow_camera.offset;
-    camera_transform.rotation = camera_transform.rotation.lerp(
-        target_rotation,
-        follow_camera.speed_transition * time.delta_seconds(),
-    );
-    camera_transform.translation = camera_transform.translation.lerp(
+    let Ok((mut camera_transform, mut follow_camera, target, projection)) =
+        camera_query.get_single_mut()
+    else {
+        return;
+    };
+    // With nothing to follow, or a target despawned this frame, hold still
+    let Some(&CameraTarget(target)) = target else {
+        return;
+    };
+    let Ok((target_transform, target_velocity)) = targets.get(target) else {
+        return;
+    };
+    let obstacles: Vec<Obstacle> = colliders
+        .iter()
+        .map(|(collider, transform)| collider.obstacle(transform))
+        .collect();
+
+    // Let the target move a little without moving the camera, and look where it's going
+    let target_position = target_transform.translation;
+    let dead_zone_center = follow_dead_zone(
+        follow_camera.dead_zone_center.unwrap_or(target_position),
         target_position,
-        follow_camera.speed_transition * time.delta_seconds(),
+        follow_camera.dead_zone,
+        follow_camera.rotation_horizontal,
+    );
+    follow_camera.dead_zone_center = Some(dead_zone_center);
+    let velocity = target_velocity.map_or(Vec3::ZERO, |velocity| velocity.linear);
+    let ahead = look_ahead(
+        velocity,
+        follow_camera.look_ahead,
+        follow_camera.look_ahead_max,
     );
+
+    // Keep the characters around the target in view as well
+    let mut framed = vec![dead_zone_center + ahead];
+    framed.extend(
+        interactables
+            .iter()
+            .filter(|&(entity, _)| entity != target)
+            .map(|(_, transform)| transform.translation)
+            .filter(|position| position.distance(target_position) <= follow_camera.framing_radius),
+    );
+    let fov = match projection {
+        Some(Projection::Perspective(perspective)) => perspective.fov,
+        _ => PerspectiveProjection::default().fov,
+    };
+    let (center, framing_zoom) =
+        frame_points(&framed, follow_camera.framing_margin, fov).unwrap_or_default();
+    let focus = center + follow_camera.offset;
+    let zoom = if framed.len() > 1 {
+        framing_zoom
+            .max(follow_camera.zoom)
+            .min(follow_camera.zoom_limit_max)
+    } else {
+        follow_camera.zoom
+    };
+
+    // Spring towards the focus and the orbit, at the same pace whatever the frame rate
+    let mut smoothing = follow_camera
+        .smoothing
+        .unwrap_or_else(|| FollowSmoothing::new(&follow_camera, focus));
+    smoothing.step(&follow_camera, focus, zoom, time.delta_seconds());
+    follow_camera.smoothing = Some(smoothing);
+    let rotation = smoothing.rotation();
+    let backwards = rotation.mul_vec3(Vec3::Z);
+
+    // Keep walls from coming between the camera and the target
+    let blocked_distance = cast_sphere(
+        smoothing.focus,
+        backwards,
+        follow_camera.collision_radius,
+        smoothing.zoom,
+        &obstacles,
+    )
+    .map_or(smoothing.zoom, |hit| hit.distance);
+    follow_camera.collision_distance = collision_distance(
+        follow_camera.collision_distance,
+        blocked_distance,
+        follow_camera.collision_recovery_speed,
+        time.delta_seconds(),
+    );
+
+    camera_transform.rotation = rotation;
+    camera_transform.translation =
+        smoothing.focus + backwards * smoothing.zoom.min(follow_camera.collision_distance);
+}
+
+// Pull in straight away when blocked, so walls never cover the target, but ease back out
+fn collision_distance(current: f32, blocked: f32, recovery_speed: f32, delta: f32) -> f32 {
+    if blocked <= current {
+        blocked
+    } else {
+        blocked - (blocked - current) * (-recovery_speed * delta).exp()
+    }
+}
+
+// Fade out meshes between the camera and its target, and back in once they're out of the way
+pub fn fade_occluders(
+    mut commands: Commands,
+    camera_query: Query<(&Transform, &FollowCamera, &CameraTarget)>,
+    targets: Query<&Transform, Without<Camera>>,
+    mut occluders: Query<(
+        Entity,
+        &BoxCollider,
+        &Transform,
+        &mut Handle<StandardMaterial>,
+        Option<&mut OcclusionFade>,
+    )>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    time: Res<Time>,
+) {
+    let Ok((camera_transform, follow_camera, &CameraTarget(target))) = camera_query.get_single()
+    else {
+        return;
+    };
+    let Ok(target_transform) = targets.get(target) else {
+        return;
+    };
+    let camera_position = camera_transform.translation;
+    let to_focus = target_transform.translation + follow_camera.offset - camera_position;
+    let distance = to_focus.length();
+    if distance < 1e-4 {
+        return;
+    }
+    let direction = to_focus / distance;
+    let fade_step = follow_camera.occlusion_fade_speed * time.delta_seconds();
+
+    for (entity, collider, transform, mut material, fade) in &mut occluders {
+        let occluding = ray_hit(camera_position, direction, &collider.obstacle(transform))
+            .map_or(false, |hit| hit.distance < distance);
+
+        let Some(mut fade) = fade else {
+            if occluding {
+                // Fade a copy, so other meshes sharing the material stay opaque
+                let Some(mut faded) = materials.get(&material).cloned() else {
+                    continue;
+                };
+                faded.alpha_mode = AlphaMode::Blend;
+                let original = std::mem::replace(&mut *material, materials.add(faded));
+                commands.entity(entity).insert(OcclusionFade {
+                    alpha: 1.0,
+                    original,
+                });
+            }
+            continue;
+        };
+
+        fade.alpha = if occluding {
+            (fade.alpha - fade_step).max(follow_camera.occluded_alpha)
+        } else {
+            fade.alpha + fade_step
+        };
+        if fade.alpha >= 1.0 {
+            *material = fade.original.clone();
+            commands.entity(entity).remove::<OcclusionFade>();
+            continue;
+        }
+        let Some(original_alpha) = materials
+            .get(&fade.original)
+            .map(|original| original.base_color.a())
+        else {
+            continue;
+        };
+        if let Some(faded) = materials.get_mut(&material) {
+            faded.base_color.set_a(original_alpha * fade.alpha);
+        }
+    }
 }
 
 pub fn camera_control(
     mut camera_query: Query<&mut FollowCamera, With<FollowCamera>>,
-    keyboard: Res<Input<KeyCode>>,
-    mouse: Res<Input<MouseButton>>,
-    mut scroll_evr: EventReader<MouseWheel>,
+    actions: Res<ActionState>,
     mut motion_evr: EventReader<MouseMotion>,
     time: Res<Time>,
 ) {
-    use bevy::input::mouse::MouseScrollUnit;
     let mut follow_camera = camera_query.single_mut();
 
-    // Scroll
-    for ev in scroll_evr.iter() {
-        match ev.unit {
-            MouseScrollUnit::Line => {
-                follow_camera.zoom -= ev.y * follow_camera.zoom_speed * time.delta_seconds();
-                follow_camera.zoom = follow_camera
-                    .zoom
-                    .clamp(follow_camera.zoom_limit_min, follow_camera.zoom_limit_max);
-            }
-            MouseScrollUnit::Pixel => {
-                follow_camera.zoom -= ev.y * follow_camera.zoom_speed * time.delta_seconds();
-                follow_camera.zoom = follow_camera
-                    .zoom
-                    .clamp(follow_camera.zoom_limit_min, follow_camera.zoom_limit_max);
-            }
-        }
+    // Zoom
+    let zoom = actions.value(Action::CameraZoom) * follow_camera.zoom_speed
+        + actions.value(Action::CameraZoomAxis) * follow_camera.zoom_axis_speed;
+    if zoom != 0.0 {
+        follow_camera.zoom -= zoom * time.delta_seconds();
+        follow_camera.zoom = follow_camera
+            .zoom
+            .clamp(follow_camera.zoom_limit_min, follow_camera.zoom_limit_max);
     }
 
-    // Rotate
-    if mouse.pressed(MouseButton::Middle)
-        || (mouse.pressed(MouseButton::Right)
-            && keyboard.any_pressed([KeyCode::LShift, KeyCode::RShift]))
-    {
+    // Rotate with a stick
+    let orbit = Vec2::new(
+        actions.value(Action::CameraOrbitX),
+        actions.value(Action::CameraOrbitY),
+    );
+    if orbit != Vec2::ZERO {
+        let rotation = orbit * follow_camera.rotation_axis_speed * time.delta_seconds();
+        follow_camera.rotation_horizontal = (follow_camera.rotation_horizontal - rotation.x).clamp(
+            follow_camera.rotation_horizontal_limit_min,
+            follow_camera.rotation_horizontal_limit_max,
+        );
+        follow_camera.rotation_vertical = (follow_camera.rotation_vertical - rotation.y).clamp(
+            follow_camera.rotation_vertical_limit_min,
+            follow_camera.rotation_vertical_limit_max,
+        );
+    }
+
+    // Rotate with the mouse
+    if actions.pressed(Action::CameraOrbit) {
         for ev in motion_evr.iter() {
             follow_camera.rotation_horizontal -=
                 ev.delta.x * follow_camera.rotation_horizontal_speed * time.delta_seconds();
@@ -95,3 +370,222 @@ pub fn camera_control(
         }
     }
 }
+
+pub fn following(mode: Res<CameraMode>) -> bool {
+    *mode == CameraMode::Follow
+}
+
+pub fn flying(mode: Res<CameraMode>) -> bool {
+    *mode == CameraMode::FreeFly
+}
+
+pub fn fixed(mode: Res<CameraMode>) -> bool {
+    *mode == CameraMode::Fixed
+}
+
+pub fn playing_cinematic(mode: Res<CameraMode>) -> bool {
+    matches!(*mode, CameraMode::Cinematic(_))
+}
+
+// Cycle through the modes, and start blending over whenever the mode changes
+pub fn switch_camera_mode(
+    mut mode: ResMut<CameraMode>,
+    mut last_mode: Local<CameraMode>,
+    actions: Res<ActionState>,
+    mut camera_query: Query<(
+        &Transform,
+        &mut CameraBlend,
+        &mut FreeFlyCamera,
+        &mut CinematicCamera,
+        Option<&mut FollowCamera>,
+    )>,
+) {
+    if actions.just_pressed(Action::CycleCameraMode) {
+        *mode = mode.next();
+    }
+    if *mode == *last_mode {
+        return;
+    }
+    let Ok((transform, mut blend, mut free_fly, mut cinematic, follow_camera)) =
+        camera_query.get_single_mut()
+    else {
+        return;
+    };
+    info!("Switched to the {:?} camera", *mode);
+
+    let duration = blend.duration;
+    blend.start(*transform, duration);
+    match *mode {
+        // Take off from where the camera is
+        CameraMode::FreeFly => {
+            let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+            free_fly.position = transform.translation;
+            free_fly.yaw = yaw;
+            free_fly.pitch = pitch;
+        }
+        CameraMode::Cinematic(_) => {
+            cinematic.time = 0.0;
+            // One cinematic straight after another goes back to the mode before both
+            if !matches!(*last_mode, CameraMode::Cinematic(_)) {
+                cinematic.return_to = last_mode.clone();
+            }
+        }
+        // Catch up from where the camera is now, not from where it was when last following
+        CameraMode::Follow => {
+            if let Some(mut follow_camera) = follow_camera {
+                follow_camera.smoothing = None;
+            }
+        }
+        CameraMode::Fixed => (),
+    }
+    *last_mode = mode.clone();
+}
+
+pub fn fly_camera(
+    mut camera_query: Query<(&mut Transform, &mut FreeFlyCamera)>,
+    actions: Res<ActionState>,
+    mut motion_evr: EventReader<MouseMotion>,
+    time: Res<Time>,
+) {
+    let Ok((mut transform, mut free_fly)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    // Look around
+    let mut look = Vec2::new(
+        actions.value(Action::CameraOrbitX),
+        actions.value(Action::CameraOrbitY),
+    ) * free_fly.stick_look_speed
+        * time.delta_seconds();
+    if actions.pressed(Action::CameraOrbit) {
+        for ev in motion_evr.iter() {
+            look += Vec2::new(ev.delta.x, -ev.delta.y) * free_fly.mouse_look_speed;
+        }
+    }
+    free_fly.yaw -= look.x;
+    free_fly.pitch = (free_fly.pitch + look.y).clamp(-1.5, 1.5);
+    let rotation = Quat::from_rotation_y(free_fly.yaw) * Quat::from_rotation_x(free_fly.pitch);
+
+    // Fly where the camera is looking
+    let mut direction = Vec2::new(actions.value(Action::MoveX), actions.value(Action::MoveY));
+    for (action, step) in [
+        (Action::MoveForward, Vec2::Y),
+        (Action::MoveBackward, Vec2::NEG_Y),
+        (Action::MoveLeft, Vec2::NEG_X),
+        (Action::MoveRight, Vec2::X),
+    ] {
+        if actions.pressed(action) {
+            direction += step;
+        }
+    }
+    let speed = if actions.pressed(Action::Sprint) {
+        free_fly.sprint_speed
+    } else {
+        free_fly.speed
+    };
+    let velocity = rotation * Vec3::new(direction.x, 0.0, -direction.y).clamp_length_max(1.0);
+    free_fly.position += velocity * speed * time.delta_seconds();
+
+    transform.translation = free_fly.position;
+    transform.rotation = rotation;
+}
+
+pub fn fixed_camera(
+    mut camera_query: Query<
+        (&mut Transform, &mut FixedCamera, Option<&CameraTarget>),
+        With<Camera>,
+    >,
+    targets: Query<&Transform, Without<Camera>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+) {
+    let Ok((mut transform, mut fixed, Some(&CameraTarget(target)))) = camera_query.get_single_mut()
+    else {
+        return;
+    };
+    let Ok(target_transform) = targets.get(target) else {
+        return;
+    };
+
+    if actions.just_pressed(Action::CameraRotateLeft) {
+        fixed.yaw -= fixed.snap_angle;
+    }
+    if actions.just_pressed(Action::CameraRotateRight) {
+        fixed.yaw += fixed.snap_angle;
+    }
+    let turned = 1.0 - (-fixed.turn_speed * time.delta_seconds()).exp();
+    fixed.current_yaw += (fixed.yaw - fixed.current_yaw) * turned;
+
+    let rotation = Quat::from_rotation_y(fixed.current_yaw) * Quat::from_rotation_x(fixed.pitch);
+    transform.translation =
+        target_transform.translation + fixed.offset + rotation * Vec3::Z * fixed.distance;
+    transform.rotation = rotation;
+}
+
+pub fn play_cinematic(
+    mut mode: ResMut<CameraMode>,
+    mut camera_query: Query<(&mut Transform, &mut CinematicCamera)>,
+    time: Res<Time>,
+) {
+    let CameraMode::Cinematic(path) = &*mode else {
+        return;
+    };
+    let Ok((mut transform, mut cinematic)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    cinematic.time += time.delta_seconds();
+    if let Some(sampled) = path.sample(cinematic.time) {
+        *transform = sampled;
+    }
+    if cinematic.time >= path.duration() {
+        *mode = cinematic.return_to.clone();
+    }
+}
+
+// Ease from where the camera was when the mode changed to where the new mode puts it
+pub fn blend_camera(mut camera_query: Query<(&mut Transform, &mut CameraBlend)>, time: Res<Time>) {
+    let Ok((mut transform, mut blend)) = camera_query.get_single_mut() else {
+        return;
+    };
+    let Some(from) = blend.from else {
+        return;
+    };
+
+    blend.elapsed += time.delta_seconds();
+    // A zero length blend cuts straight over
+    let progress = if blend.current_duration > 0.0 {
+        (blend.elapsed / blend.current_duration).min(1.0)
+    } else {
+        1.0
+    };
+    let eased = progress * progress * (3.0 - 2.0 * progress);
+    transform.translation = from.translation.lerp(transform.translation, eased);
+    transform.rotation = from.rotation.slerp(transform.rotation, eased);
+    if progress >= 1.0 {
+        blend.from = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_cameras_pull_in_straight_away() {
+        assert_eq!(collision_distance(8.0, 3.0, 3.0, 0.016), 3.0);
+    }
+
+    #[test]
+    fn unblocked_cameras_ease_back_out() {
+        let mut distance = 3.0;
+        let mut previous = distance;
+        for _ in 0..60 {
+            distance = collision_distance(distance, 8.0, 3.0, 1.0 / 60.0);
+            assert!(distance > previous && distance < 8.0);
+            previous = distance;
+        }
+        // After a second, most of the way back out
+        assert!(distance > 7.5);
+    }
+}
@@ -1,14 +1,69 @@
+use std::f32::consts::PI;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 use crate::animation::components::*;
+use crate::animation::state_machine::AnimationStateMachine;
+use crate::physics::components::CharacterController;
 
 #[derive(Component)]
 pub struct Player;
 
+// A character the player can talk to or use, which the camera keeps in view when close by
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Interactable {
+    // How close the player has to be to interact with it
+    pub distance: f32,
+}
+
+impl Default for Interactable {
+    fn default() -> Self {
+        Self { distance: 1.5 }
+    }
+}
+
+// How the player steers their character
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum PlayerControlMode {
+    // Move and sprint with the keys or a stick, relative to the camera
+    #[default]
+    Direct,
+    // Click on the ground to walk there, around obstacles when `pathfinding` is set
+    ClickToMove {
+        pathfinding: bool,
+    },
+}
+
+// Shows where the player is walking to in click to move mode
 #[derive(Component)]
+pub struct DestinationMarker;
+
+#[derive(Component, Clone, Deserialize)]
+#[serde(default)]
 pub struct Movable {
     pub walk_speed: f32,
     pub run_speed: f32,
+    // How quickly the character speeds up, in metres per second squared
+    pub acceleration: f32,
+    // How quickly the character slows down when stopping or slowing to a walk
+    pub friction: f32,
+    // How quickly the character changes direction, in radians per second
+    pub turn_rate: f32,
+}
+
+impl Movable {
+    // Top speed when heading for a spot `distance` away, slowing down on arrival
+    // so friction doesn't carry the character past it
+    pub fn arrival_speed(&self, speed: f32, distance: f32) -> f32 {
+        speed.min((2.0 * self.friction * distance).sqrt())
+    }
+
+    // Faster than this shows the run animation, half way between walking and running
+    pub fn run_animation_speed(&self) -> f32 {
+        (self.walk_speed + self.run_speed) * 0.5
+    }
 }
 
 impl Default for Movable {
@@ -16,6 +71,66 @@ impl Default for Movable {
         Self {
             walk_speed: 2.0,
             run_speed: 5.0,
+            acceleration: 20.0,
+            friction: 16.0,
+            turn_rate: 4.0 * PI,
+        }
+    }
+}
+
+// Horizontal velocity of a character, smoothed towards the target set by the player or AI
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Velocity {
+    pub linear: Vec3,
+    pub target: Vec3,
+}
+
+// Lets a character jump, forgiving presses slightly before landing or after leaving a ledge
+#[derive(Component, Reflect)]
+pub struct Jump {
+    // Upwards speed at the start of a jump, in metres per second
+    pub speed: f32,
+    // How long after leaving the ground a jump is still allowed
+    pub coyote_time: f32,
+    // How long a jump pressed in the air is remembered, to jump again on landing
+    pub buffer_time: f32,
+    // Set by the player or AI, cleared once handled
+    pub requested: bool,
+    pub time_since_grounded: f32,
+    pub time_since_requested: f32,
+}
+
+impl Default for Jump {
+    fn default() -> Self {
+        Self {
+            speed: 4.5,
+            coyote_time: 0.1,
+            buffer_time: 0.15,
+            requested: false,
+            time_since_grounded: f32::INFINITY,
+            time_since_requested: f32::INFINITY,
+        }
+    }
+}
+
+// Dark circle on the ground below a character, to show how high it is in the air
+#[derive(Component, Reflect)]
+pub struct BlobShadow {
+    pub radius: f32,
+    // Highest the character can be above the ground before the shadow disappears
+    pub max_height: f32,
+    // The child entity showing the shadow, once spawned
+    #[reflect(ignore)]
+    pub entity: Option<Entity>,
+}
+
+impl Default for BlobShadow {
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            max_height: 6.0,
+            entity: None,
         }
     }
 }
@@ -23,16 +138,37 @@ impl Default for Movable {
 #[derive(Bundle)]
 pub struct CharacterBundle {
     pub movable: Movable,
+    pub velocity: Velocity,
+    pub jump: Jump,
+    pub blob_shadow: BlobShadow,
     pub turn_to_camera: TurnTowardCamera,
     pub animated_character: AnimatedCharacter,
+    pub state_machine: AnimationStateMachine,
+    pub controller: CharacterController,
+}
+
+impl CharacterBundle {
+    pub fn new(movable: Movable) -> Self {
+        Self {
+            state_machine: AnimationStateMachine::locomotion(movable.run_animation_speed()),
+            movable,
+            ..default()
+        }
+    }
 }
 
 impl Default for CharacterBundle {
     fn default() -> Self {
+        let movable = Movable::default();
         Self {
-            movable: Movable { ..default() },
+            state_machine: AnimationStateMachine::locomotion(movable.run_animation_speed()),
+            movable,
+            velocity: Velocity::default(),
+            jump: Jump::default(),
+            blob_shadow: BlobShadow::default(),
             turn_to_camera: TurnTowardCamera(true),
             animated_character: AnimatedCharacter { ..default() },
+            controller: CharacterController::default(),
         }
     }
 }
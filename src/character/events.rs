@@ -0,0 +1,16 @@
+use bevy::prelude::*;
+
+use super::prefab::{CharacterPrefab, PrefabComponent};
+
+// Send this to spawn a character from a prefab. It is spawned as soon as the prefab,
+// its sprite sheet and animations are loaded
+#[derive(Clone)]
+pub struct SpawnCharacter {
+    pub prefab: Handle<CharacterPrefab>,
+    pub name: String,
+    // Where the character's feet are
+    pub position: Vec3,
+    pub heading: Vec3,
+    // Added on top of the prefab's own components
+    pub components: Vec<PrefabComponent>,
+}
@@ -0,0 +1,75 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use super::prefab::PrefabComponent;
+use crate::camera::cinematic::CinematicPath;
+
+// Where characters are placed, loaded from a `.level.ron` file
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "b7245f0e-2c1d-4e83-a6f9-5d8c3e0a1b92"]
+pub struct Level {
+    pub characters: Vec<CharacterPlacement>,
+    // Camera move played when the level starts
+    #[serde(default)]
+    pub intro: Option<CinematicPath>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CharacterPlacement {
+    // Path of a `.character.ron` prefab
+    pub prefab: String,
+    pub name: String,
+    // Where the character's feet are
+    pub position: Vec3,
+    // Direction the character faces at first
+    #[serde(default = "default_heading")]
+    pub heading: Vec3,
+    // Added on top of the prefab's own components
+    #[serde(default)]
+    pub components: Vec<PrefabComponent>,
+}
+
+fn default_heading() -> Vec3 {
+    Vec3::X
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level = ron::de::from_bytes::<Level>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn village_level_parses() {
+        let level: Level =
+            ron::de::from_str(include_str!("../../assets/Village.level.ron")).unwrap();
+
+        assert_eq!(level.characters.len(), 4);
+        assert!(level
+            .characters
+            .iter()
+            .any(|placement| placement.name == "Player"));
+    }
+}
@@ -1,20 +1,76 @@
 use bevy::prelude::*;
 
 pub mod components;
+pub mod events;
+pub mod level;
+pub mod prefab;
 mod systems;
 
+use components::*;
+use events::SpawnCharacter;
+use level::{Level, LevelLoader};
+use prefab::{CharacterPrefab, CharacterPrefabLoader};
 use systems::*;
 
+use crate::animation::AnimateCharactersSet;
+use crate::physics::PhysicsSet;
 use crate::GameState;
 
+// Moves characters towards their Velocity target. Anything setting the target, like
+// player input or AI, runs before this
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MoveCharacterSet;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app
+            // Register types
+            .register_type::<Velocity>()
+            .register_type::<Interactable>()
+            .register_type::<Jump>()
+            .register_type::<BlobShadow>()
+            .init_resource::<PlayerControlMode>()
+            // Assets and events
+            .add_asset::<CharacterPrefab>()
+            .init_asset_loader::<CharacterPrefabLoader>()
+            .add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_event::<SpawnCharacter>()
             // On enter
-            .add_systems((spawn_player, spawn_npcs).in_schedule(OnEnter(GameState::Playing)))
+            .add_systems(
+                (spawn_level, spawn_destination_marker).in_schedule(OnEnter(GameState::Playing)),
+            )
             // On update
-            .add_system(control_player.in_set(OnUpdate(GameState::Playing)));
+            .add_systems(
+                (
+                    toggle_control_mode.before(control_player),
+                    control_player
+                        .before(MoveCharacterSet)
+                        .before(AnimateCharactersSet),
+                    interact
+                        .before(MoveCharacterSet)
+                        .before(AnimateCharactersSet),
+                    click_to_move.before(MoveCharacterSet),
+                    jump_characters.after(control_player).before(PhysicsSet),
+                    move_character
+                        .in_set(MoveCharacterSet)
+                        .before(PhysicsSet)
+                        .before(AnimateCharactersSet),
+                )
+                    .in_set(OnUpdate(GameState::Playing)),
+            )
+            .add_systems(
+                (
+                    sync_animation_parameters.before(AnimateCharactersSet),
+                    place_blob_shadows,
+                    hide_destination_marker,
+                )
+                    .after(PhysicsSet)
+                    .in_set(OnUpdate(GameState::Playing)),
+            )
+            .add_system(spawn_characters.in_set(OnUpdate(GameState::Playing)))
+            .add_system(spawn_blob_shadows.in_set(OnUpdate(GameState::Playing)));
     }
 }
@@ -0,0 +1,193 @@
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use bevy_mod_aseprite::Aseprite;
+use serde::Deserialize;
+
+use super::components::Movable;
+use crate::ai::components::Behaviour;
+use crate::animation::assets::AnimationSet;
+use crate::component_sprite::components::SpriteLayer;
+
+// Everything needed to spawn a character, loaded from a `.character.ron` file
+#[derive(TypeUuid)]
+#[uuid = "3e9b6d1c-4a7f-4f2e-8c55-91d0b2a6e417"]
+pub struct CharacterPrefab {
+    pub sprite: CharacterSprite,
+    pub pixels_per_metre: f32,
+    pub pivot: Vec2,
+    pub feet_offset: f32,
+    pub movable: Movable,
+    pub components: Vec<PrefabComponent>,
+}
+
+// Where a character's frames and animations come from
+pub enum CharacterSprite {
+    SpriteSheet {
+        atlas: Handle<TextureAtlas>,
+        animation_set: Handle<AnimationSet>,
+    },
+    // Uses the atlas the Aseprite file is cut into, animated from its tags
+    Aseprite(Handle<Aseprite>),
+}
+
+// The file format, with paths instead of handles
+#[derive(Deserialize)]
+pub struct CharacterPrefabDefinition {
+    pub sprite: SpriteDefinition,
+    #[serde(default = "default_pixels_per_metre")]
+    pub pixels_per_metre: f32,
+    // Point of the sprite placed at the character's position, (0.5, 0.0) is bottom centre
+    #[serde(default = "default_pivot")]
+    pub pivot: Vec2,
+    // Empty space in the sprite below the character's feet, in metres
+    #[serde(default)]
+    pub feet_offset: f32,
+    #[serde(default)]
+    pub movable: Movable,
+    #[serde(default)]
+    pub components: Vec<PrefabComponent>,
+}
+
+#[derive(Deserialize)]
+pub enum SpriteDefinition {
+    // A sprite sheet cut into a grid, animated by an `.anim.ron` AnimationSet
+    SpriteSheet {
+        path: String,
+        // Size of a single frame in the sprite sheet, in pixels
+        tile_size: Vec2,
+        columns: usize,
+        rows: usize,
+        animation_set: String,
+    },
+    // An Aseprite file with tags named `<state>_<direction>`
+    Aseprite(String),
+}
+
+fn default_pixels_per_metre() -> f32 {
+    28.0
+}
+
+fn default_pivot() -> Vec2 {
+    Vec2::new(0.5, 0.0)
+}
+
+// Optional components a prefab or a placement in a level can add to a character
+#[derive(Deserialize, Clone)]
+pub enum PrefabComponent {
+    Player,
+    PathFollower,
+    Ai(Behaviour),
+    Interactable,
+    // Pick from eight directions instead of four, when the animation set has them
+    EightDirections,
+    // Stacked on top of the character's sprite, to vary characters sharing a sprite sheet
+    Layers(Vec<LayerDefinition>),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LayerDefinition {
+    pub layer: SpriteLayer,
+    // Laid out like the character's own sprite sheet
+    pub texture: String,
+}
+
+#[derive(Default)]
+pub struct CharacterPrefabLoader;
+
+impl AssetLoader for CharacterPrefabLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let definition = ron::de::from_bytes::<CharacterPrefabDefinition>(bytes)?;
+
+            let (sprite, dependency) = match &definition.sprite {
+                SpriteDefinition::SpriteSheet {
+                    path,
+                    tile_size,
+                    columns,
+                    rows,
+                    animation_set,
+                } => {
+                    // The sprite sheet is cut into an atlas as part of the prefab
+                    let sprite_sheet_path = AssetPath::from(path.as_str()).to_owned();
+                    let atlas = TextureAtlas::from_grid(
+                        load_context.get_handle(sprite_sheet_path.get_id()),
+                        *tile_size,
+                        *columns,
+                        *rows,
+                        None,
+                        None,
+                    );
+                    let atlas = load_context.set_labeled_asset(
+                        "atlas",
+                        LoadedAsset::new(atlas).with_dependency(sprite_sheet_path),
+                    );
+                    let animation_set_path = AssetPath::from(animation_set.as_str()).to_owned();
+                    let sprite = CharacterSprite::SpriteSheet {
+                        atlas,
+                        animation_set: load_context.get_handle(animation_set_path.get_id()),
+                    };
+                    (sprite, animation_set_path)
+                }
+                SpriteDefinition::Aseprite(path) => {
+                    let aseprite_path = AssetPath::from(path.as_str()).to_owned();
+                    let sprite =
+                        CharacterSprite::Aseprite(load_context.get_handle(aseprite_path.get_id()));
+                    (sprite, aseprite_path)
+                }
+            };
+
+            let prefab = CharacterPrefab {
+                sprite,
+                pixels_per_metre: definition.pixels_per_metre,
+                pivot: definition.pivot,
+                feet_offset: definition.feet_offset,
+                movable: definition.movable,
+                components: definition.components,
+            };
+            load_context.set_default_asset(LoadedAsset::new(prefab).with_dependency(dependency));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["character.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefabs_parse() {
+        for text in [
+            include_str!("../../assets/Player.character.ron"),
+            include_str!("../../assets/Villager.character.ron"),
+        ] {
+            let definition: CharacterPrefabDefinition = ron::de::from_str(text).unwrap();
+            let SpriteDefinition::SpriteSheet {
+                path,
+                columns,
+                rows,
+                ..
+            } = definition.sprite
+            else {
+                panic!("expected a sprite sheet");
+            };
+            assert_eq!(path, "Character.png");
+            assert_eq!(columns * rows, 36);
+        }
+
+        let definition: CharacterPrefabDefinition =
+            ron::de::from_str(include_str!("../../assets/Traveller.character.ron")).unwrap();
+        assert!(
+            matches!(definition.sprite, SpriteDefinition::Aseprite(path) if path == "Traveller.aseprite")
+        );
+    }
+}
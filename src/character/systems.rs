@@ -1,305 +1,255 @@
 use std::f32::consts::PI;
 use std::f32::EPSILON;
 
+use bevy::asset::LoadState;
 use bevy::prelude::*;
-use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+use bevy_mod_aseprite::Aseprite;
 use bevy_sprite3d::{AtlasSprite3d, Sprite3dParams};
 
 use super::components::*;
-use crate::animation::components::Direction;
+use super::events::SpawnCharacter;
+use super::level::Level;
+use super::prefab::{CharacterPrefab, CharacterSprite, PrefabComponent};
+use crate::ai::components::Ai;
 use crate::animation::components::*;
-use crate::animation::systems::*;
-use crate::ImageAssets;
+use crate::animation::state_machine::AnimationStateMachine;
+use crate::camera::components::CameraMode;
+use crate::component_sprite::components::{ComponentSprite, ComponentSpriteLayer};
+use crate::input::actions::{Action, ActionState};
+use crate::navigation::components::PathFollower;
+use crate::navigation::grid::NavGrid;
+use crate::physics::collision::{cast_ray, Obstacle};
+use crate::physics::components::{BoxCollider, CharacterController};
+use crate::save::components::Persistent;
+use crate::LevelAssets;
 
-pub fn spawn_player(
-    mut commands: Commands,
-    images: Res<ImageAssets>,
-    mut sprite_params: Sprite3dParams,
+// Send the characters placed in the level to be spawned, and play its intro
+pub fn spawn_level(
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    mut spawn_events: EventWriter<SpawnCharacter>,
+    mut camera_mode: ResMut<CameraMode>,
 ) {
-    commands
-        .spawn(
-            AtlasSprite3d {
-                atlas: images.character_sheet.clone(),
-                pixels_per_metre: 28.0,
-                partial_alpha: true,
-                unlit: false,
-                index: 0,
-                pivot: Some(Vec2::new(0.5, 0.0)),
-                transform: Transform::from_xyz(1.0, -0.15, 2.0),
-                ..default()
-            }
-            .bundle(&mut sprite_params),
-        )
-        .insert(Name::new("Player"))
-        .insert(Player)
-        .insert(CharacterBundle {
-            animated_character: AnimatedCharacter {
-                heading: Vec3::new(1.0, 0.0, 0.0),
-                animations: HashMap::from([
-                    (
-                        (AnimationState::Idle, Direction::Down),
-                        Animation {
-                            frames: [0].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Right),
-                        Animation {
-                            frames: [1].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Up),
-                        Animation {
-                            frames: [2].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Left),
-                        Animation {
-                            frames: [3].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Down),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 4).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Right),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 5).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Up),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 6).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Left),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 7).to_vec(),
-                            ..default()
-                        },
-                    ),
-                ]),
-                ..default()
-            },
-            ..default()
+    let Some(level) = levels.get(&level_assets.level) else {
+        warn!("Level isn't loaded, no characters to spawn");
+        return;
+    };
+
+    for placement in level.characters.iter() {
+        spawn_events.send(SpawnCharacter {
+            prefab: asset_server.load(placement.prefab.as_str()),
+            name: placement.name.clone(),
+            position: placement.position,
+            heading: placement.heading,
+            components: placement.components.clone(),
         });
+    }
+    if let Some(intro) = &level.intro {
+        *camera_mode = CameraMode::Cinematic(intro.clone());
+    }
 }
 
-pub fn spawn_npcs(
+// Spawn characters from their prefabs, keeping the requests around until the prefab
+// and its sprite sheet are loaded
+pub fn spawn_characters(
     mut commands: Commands,
-    images: Res<ImageAssets>,
+    mut spawn_events: EventReader<SpawnCharacter>,
+    mut pending: Local<Vec<SpawnCharacter>>,
+    prefabs: Res<Assets<CharacterPrefab>>,
+    aseprites: Res<Assets<Aseprite>>,
+    asset_server: Res<AssetServer>,
     mut sprite_params: Sprite3dParams,
 ) {
-    commands
-        .spawn(
-            AtlasSprite3d {
-                atlas: images.character_sheet.clone(),
-                pixels_per_metre: 28.0,
-                partial_alpha: true,
-                unlit: false,
-                index: 0,
-                pivot: Some(Vec2::new(0.5, 0.0)),
-                transform: Transform::from_xyz(-2.0, -0.15, -1.3),
-                ..default()
+    pending.extend(spawn_events.iter().cloned());
+
+    let mut waiting = Vec::new();
+    for event in pending.drain(..) {
+        let Some(prefab) = prefabs.get(&event.prefab) else {
+            if asset_server.get_load_state(&event.prefab) == LoadState::Failed {
+                error!("Couldn't spawn '{}', its prefab failed to load", event.name);
+            } else {
+                waiting.push(event);
             }
-            .bundle(&mut sprite_params),
-        )
-        .insert(Name::new("Brown"))
-        .insert(CharacterBundle {
-            animated_character: AnimatedCharacter {
-                heading: Vec3::new(0.8, 0.0, -0.2).normalize(),
-                animations: HashMap::from([
-                    (
-                        (AnimationState::Idle, Direction::Down),
-                        Animation {
-                            frames: [0].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Right),
-                        Animation {
-                            frames: [1].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Up),
-                        Animation {
-                            frames: [2].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Left),
-                        Animation {
-                            frames: [3].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Down),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 4).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Right),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 5).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Up),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 6).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Left),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 7).to_vec(),
-                            ..default()
-                        },
-                    ),
-                ]),
-                ..default()
-            },
+            continue;
+        };
+        let atlas = match &prefab.sprite {
+            CharacterSprite::SpriteSheet { atlas, .. } => Some(atlas.clone()),
+            CharacterSprite::Aseprite(aseprite) => aseprites
+                .get(aseprite)
+                .map(|aseprite| aseprite.atlas().clone()),
+        };
+        let Some(atlas) = atlas.filter(|atlas| {
+            sprite_params
+                .atlases
+                .get(atlas)
+                .map_or(false, |atlas| sprite_params.images.contains(&atlas.texture))
+        }) else {
+            waiting.push(event);
+            continue;
+        };
+
+        let mut animated_character = AnimatedCharacter {
+            heading: event.heading.try_normalize().unwrap_or(Vec3::Z),
+            max_movement_speed: prefab.movable.run_speed,
             ..default()
-        });
+        };
+        let components = prefab.components.iter().chain(event.components.iter());
+        let mut layers = Vec::new();
+        for component in components.clone() {
+            match component {
+                PrefabComponent::EightDirections => animated_character.eight_directions = true,
+                // Layers from the prefab and the placement are stacked together
+                PrefabComponent::Layers(definitions) => {
+                    layers.extend(definitions.iter().map(|definition| ComponentSpriteLayer {
+                        layer: definition.layer,
+                        texture: asset_server.load(definition.texture.as_str()),
+                    }));
+                }
+                _ => (),
+            }
+        }
 
-    commands
-        .spawn(
+        let mut entity = commands.spawn(
             AtlasSprite3d {
-                atlas: images.character_sheet.clone(),
-                pixels_per_metre: 28.0,
+                atlas: atlas.clone(),
+                pixels_per_metre: prefab.pixels_per_metre,
                 partial_alpha: true,
                 unlit: false,
                 index: 0,
-                pivot: Some(Vec2::new(0.5, 0.0)),
-                transform: Transform::from_xyz(-0.8, -0.15, -1.6),
+                pivot: Some(prefab.pivot),
+                // The sprite's pivot is below the feet by the empty space under them
+                transform: Transform::from_translation(
+                    event.position - Vec3::Y * prefab.feet_offset,
+                ),
                 ..default()
             }
             .bundle(&mut sprite_params),
-        )
-        .insert(Name::new("Pink"))
-        .insert(CharacterBundle {
-            animated_character: AnimatedCharacter {
-                heading: Vec3::new(-1.8, 0.0, 0.2).normalize(),
-                animations: HashMap::from([
-                    (
-                        (AnimationState::Idle, Direction::Down),
-                        Animation {
-                            frames: [0].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Right),
-                        Animation {
-                            frames: [1].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Up),
-                        Animation {
-                            frames: [2].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Idle, Direction::Left),
-                        Animation {
-                            frames: [3].to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Down),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 4).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Right),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 5).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Up),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 6).to_vec(),
-                            ..default()
-                        },
-                    ),
-                    (
-                        (AnimationState::Walk, Direction::Left),
-                        Animation {
-                            frames: core::array::from_fn::<usize, 8, _>(|i| i * 4 + 7).to_vec(),
-                            ..default()
-                        },
-                    ),
-                ]),
-                ..default()
-            },
-            ..default()
-        });
+        );
+        entity
+            .insert(Name::new(event.name.clone()))
+            .insert(Persistent)
+            .insert(CharacterBundle {
+                animated_character,
+                controller: CharacterController {
+                    feet_offset: prefab.feet_offset,
+                    ..default()
+                },
+                ..CharacterBundle::new(prefab.movable.clone())
+            });
+        // The animations are built once the animation set or Aseprite file is loaded
+        match &prefab.sprite {
+            CharacterSprite::SpriteSheet { animation_set, .. } => {
+                entity.insert(animation_set.clone());
+            }
+            CharacterSprite::Aseprite(aseprite) => {
+                entity.insert(aseprite.clone());
+            }
+        }
+        if !layers.is_empty() {
+            entity.insert(ComponentSprite::new(
+                layers,
+                atlas,
+                prefab.pixels_per_metre,
+                prefab.pivot,
+            ));
+        }
+        for component in components {
+            match component {
+                PrefabComponent::Player => {
+                    entity.insert(Player);
+                }
+                PrefabComponent::PathFollower => {
+                    entity.insert(PathFollower::default());
+                }
+                PrefabComponent::Ai(behaviour) => {
+                    entity.insert(Ai::new(behaviour.clone()));
+                }
+                PrefabComponent::Interactable => {
+                    entity.insert(Interactable::default());
+                }
+                PrefabComponent::EightDirections | PrefabComponent::Layers(_) => (),
+            }
+        }
+    }
+    *pending = waiting;
 }
 
 pub fn control_player(
     mut player_query: Query<
-        (&mut Transform, &Movable, &mut AnimatedCharacter),
-        (With<Player>, Without<Camera>),
+        (
+            &Movable,
+            &mut Velocity,
+            &mut Jump,
+            &mut AnimationStateMachine,
+        ),
+        With<Player>,
     >,
     camera_query: Query<&Transform, With<Camera>>,
-    keyboard: Res<Input<KeyCode>>,
-    time: Res<Time>,
+    actions: Res<ActionState>,
+    control_mode: Res<PlayerControlMode>,
+    camera_mode: Res<CameraMode>,
 ) {
-    let (mut character_transform, movable, mut animated_character) = player_query.single_mut();
+    let Ok((movable, mut velocity, mut jump, mut state_machine)) = player_query.get_single_mut()
+    else {
+        return;
+    };
+    // The movement keys fly the debug camera instead
+    if *camera_mode == CameraMode::FreeFly {
+        velocity.target = Vec3::ZERO;
+        state_machine.parameters.attacking = false;
+        return;
+    }
+    if actions.just_pressed(Action::Jump) {
+        jump.requested = true;
+    }
+    let parameters = &mut state_machine.parameters;
+    // Only on the frame it's pressed, the attack then plays through. Holding the button
+    // would otherwise leave the character stuck in the finished attack
+    parameters.attacking = actions.just_pressed(Action::Attack);
+    if actions.just_pressed(Action::Sit) {
+        parameters.sitting = !parameters.sitting;
+    }
+    // Jumping or attacking gets back up, as does walking off in move_character
+    if jump.requested || parameters.attacking {
+        parameters.sitting = false;
+    }
+    // Walking is left to the PathFollower
+    if *control_mode != PlayerControlMode::Direct {
+        return;
+    }
+
     let mut direction = Vec3::splat(0.0);
     let mut speed = movable.walk_speed;
 
-    if keyboard.pressed(KeyCode::W) {
+    if actions.pressed(Action::MoveForward) {
         direction.z += 1.0;
     }
-    if keyboard.pressed(KeyCode::S) {
+    if actions.pressed(Action::MoveBackward) {
         direction.z -= 1.0;
     }
-    if keyboard.pressed(KeyCode::A) {
+    if actions.pressed(Action::MoveLeft) {
         direction.x -= 1.0;
     }
-    if keyboard.pressed(KeyCode::D) {
+    if actions.pressed(Action::MoveRight) {
         direction.x += 1.0;
     }
+    // Analog stick, when no keys are held
+    if direction.length_squared() < EPSILON {
+        direction = Vec3::new(
+            actions.value(Action::MoveX),
+            0.0,
+            actions.value(Action::MoveY),
+        );
+        speed = analog_speed(movable, direction.length());
+    }
     // No movement
     if direction.length_squared() < EPSILON {
-        if animated_character.animation_state != AnimationState::Idle {
-            set_animation_state(&mut animated_character, AnimationState::Idle);
-        }
+        velocity.target = Vec3::ZERO;
         return;
     }
-    if keyboard.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+    if actions.pressed(Action::Sprint) {
         speed = movable.run_speed;
     }
 
@@ -311,30 +261,367 @@ pub fn control_player(
     let direction_vector =
         Vec3::new(horizontal.x + horizontal.z, 0.0, vertical.x + vertical.z).normalize();
 
-    let move_force = direction_vector * speed * time.delta_seconds();
-    move_character(
-        &mut character_transform,
-        &move_force,
-        Some(&mut animated_character),
-    );
+    velocity.target = direction_vector * speed;
 }
 
-fn move_character(
-    character_transform: &mut Transform,
-    move_force: &Vec3,
-    animated_character_option: Option<&mut AnimatedCharacter>,
+// Play the interact animation next to a character to talk to, turning towards it
+pub fn interact(
+    actions: Res<ActionState>,
+    mut player_query: Query<
+        (
+            &Transform,
+            &mut AnimatedCharacter,
+            &mut AnimationStateMachine,
+        ),
+        With<Player>,
+    >,
+    interactables: Query<(&Transform, &Interactable), Without<Player>>,
 ) {
-    // Apply the heading and update the character direction if necessary
-    if animated_character_option.is_some() {
-        let mut animated_character = animated_character_option.unwrap();
+    let Ok((transform, mut animated_character, mut state_machine)) = player_query.get_single_mut()
+    else {
+        return;
+    };
+    // Only set for the frame it's pressed, the animation then plays through
+    state_machine.parameters.interacting = false;
+    if !actions.just_pressed(Action::Interact) {
+        return;
+    }
 
-        animated_character.heading = *move_force;
-        if animated_character.animation_state != AnimationState::Walk {
-            set_animation_state(&mut animated_character, AnimationState::Walk);
+    let closest = interactables
+        .iter()
+        .map(|(interactable_transform, interactable)| {
+            let offset = (interactable_transform.translation - transform.translation)
+                * Vec3::new(1.0, 0.0, 1.0);
+            (offset, interactable.distance)
+        })
+        .filter(|(offset, distance)| offset.length() <= *distance)
+        .min_by(|(a, _), (b, _)| a.length().total_cmp(&b.length()));
+    if let Some((offset, _)) = closest {
+        if offset.length_squared() > EPSILON {
+            animated_character.heading = offset;
         }
+        state_machine.parameters.interacting = true;
     }
+}
+
+pub fn jump_characters(mut query: Query<(&mut Jump, &mut CharacterController)>, time: Res<Time>) {
+    for (mut jump, mut controller) in &mut query {
+        step_jump(&mut jump, &mut controller, time.delta_seconds());
+    }
+}
 
-    character_transform.translation += *move_force;
+fn step_jump(jump: &mut Jump, controller: &mut CharacterController, delta: f32) {
+    if jump.requested {
+        jump.requested = false;
+        jump.time_since_requested = 0.0;
+    } else {
+        jump.time_since_requested += delta;
+    }
+    if controller.grounded {
+        jump.time_since_grounded = 0.0;
+    } else {
+        jump.time_since_grounded += delta;
+    }
+
+    if jump.time_since_requested <= jump.buffer_time && jump.time_since_grounded <= jump.coyote_time
+    {
+        controller.vertical_velocity = jump.speed;
+        // Use up both, so a single press can't jump twice
+        jump.time_since_requested = f32::INFINITY;
+        jump.time_since_grounded = f32::INFINITY;
+    }
+}
+
+pub fn toggle_control_mode(
+    mut control_mode: ResMut<PlayerControlMode>,
+    mut player_query: Query<(&mut PathFollower, &mut Velocity), With<Player>>,
+    actions: Res<ActionState>,
+) {
+    let toggle_mode = actions.just_pressed(Action::ToggleControlMode);
+    let toggle_pathfinding = actions.just_pressed(Action::TogglePathfinding);
+    *control_mode = match *control_mode {
+        PlayerControlMode::Direct if toggle_mode => {
+            PlayerControlMode::ClickToMove { pathfinding: true }
+        }
+        PlayerControlMode::ClickToMove { .. } if toggle_mode => PlayerControlMode::Direct,
+        PlayerControlMode::ClickToMove { pathfinding } if toggle_pathfinding => {
+            PlayerControlMode::ClickToMove {
+                pathfinding: !pathfinding,
+            }
+        }
+        _ => return,
+    };
+    info!("Switched to {:?} control", *control_mode);
+
+    // Don't keep walking to the last clicked spot
+    if let Ok((mut path_follower, mut velocity)) = player_query.get_single_mut() {
+        path_follower.stop();
+        velocity.target = Vec3::ZERO;
+    }
+}
+
+// Walk the player to the ground under the cursor when clicking
+#[allow(clippy::too_many_arguments)]
+pub fn click_to_move(
+    control_mode: Res<PlayerControlMode>,
+    actions: Res<ActionState>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    colliders: Query<(&BoxCollider, &Transform), Without<DestinationMarker>>,
+    grid: Option<Res<NavGrid>>,
+    mut player_query: Query<(&mut PathFollower, &GlobalTransform), With<Player>>,
+    mut marker_query: Query<(&mut Transform, &mut Visibility), With<DestinationMarker>>,
+) {
+    let PlayerControlMode::ClickToMove { pathfinding } = *control_mode else {
+        return;
+    };
+    if !actions.just_pressed(Action::MoveToCursor) {
+        return;
+    }
+    let Some(cursor) = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+    else {
+        return;
+    };
+    let (camera, camera_transform) = camera_query.single();
+    let Some(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+    let Ok((mut path_follower, player_transform)) = player_query.get_single_mut() else {
+        return;
+    };
+    let Some(grid) = grid else {
+        return;
+    };
+
+    let obstacles: Vec<Obstacle> = colliders
+        .iter()
+        .map(|(collider, transform)| collider.obstacle(transform))
+        .collect();
+    let Some(hit) = cast_ray(ray.origin, ray.direction, 100.0, &obstacles) else {
+        return;
+    };
+    // Only ground the player can walk to, not the sides or tops of walls
+    let walkable = grid
+        .cell_at(hit.point)
+        .and_then(|cell| grid.ground_height(cell))
+        .map_or(false, |height| (height - hit.point.y).abs() < 0.1);
+    if !walkable
+        || grid
+            .find_path(player_transform.translation(), hit.point)
+            .is_none()
+    {
+        return;
+    }
+
+    path_follower.stop();
+    path_follower.use_nav_grid = pathfinding;
+    path_follower.go_to(hit.point);
+
+    if let Ok((mut marker_transform, mut visibility)) = marker_query.get_single_mut() {
+        marker_transform.translation = hit.point + Vec3::Y * 0.02;
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub fn spawn_destination_marker(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Torus {
+                radius: 0.2,
+                ring_radius: 0.025,
+                ..default()
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(1.0, 0.85, 0.3),
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(DestinationMarker)
+        .insert(Name::new("Destination Marker"));
+}
+
+// Hide the marker once the player gets there, or stops going there
+pub fn hide_destination_marker(
+    player_query: Query<&PathFollower, With<Player>>,
+    mut marker_query: Query<&mut Visibility, With<DestinationMarker>>,
+) {
+    let Ok(path_follower) = player_query.get_single() else {
+        return;
+    };
+    if path_follower.destination.is_none() {
+        for mut visibility in &mut marker_query {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+// Half way walks, all the way runs, and in between speeds up from one to the other
+fn analog_speed(movable: &Movable, tilt: f32) -> f32 {
+    let tilt = tilt.min(1.0);
+    if tilt <= 0.5 {
+        movable.walk_speed * tilt * 2.0
+    } else {
+        movable.walk_speed + (movable.run_speed - movable.walk_speed) * (tilt - 0.5) * 2.0
+    }
+}
+
+// Speed up, slow down and turn characters towards their target velocity,
+// the CharacterController does the actual moving
+pub fn move_character(
+    mut query: Query<(
+        &Movable,
+        &mut Velocity,
+        &mut CharacterController,
+        Option<&mut AnimatedCharacter>,
+        Option<&mut AnimationStateMachine>,
+    )>,
+    time: Res<Time>,
+) {
+    for (movable, mut velocity, mut controller, animated_character, state_machine) in &mut query {
+        velocity.linear = step_velocity(
+            movable,
+            velocity.linear,
+            velocity.target,
+            time.delta_seconds(),
+        );
+        controller.desired_velocity = velocity.linear;
+
+        // Apply the heading, the animation state is picked by the AnimationStateMachine
+        if let Some(mut animated_character) = animated_character {
+            if velocity.linear.length_squared() > EPSILON {
+                animated_character.heading = velocity.linear;
+            }
+        }
+        if let Some(mut state_machine) = state_machine {
+            state_machine.parameters.speed = velocity.linear.length();
+            if velocity.target.length_squared() > EPSILON {
+                state_machine.parameters.sitting = false;
+            }
+        }
+    }
+}
+
+fn step_velocity(movable: &Movable, current: Vec3, target: Vec3, delta: f32) -> Vec3 {
+    let speed = current.length();
+    let target_speed = target.length();
+
+    // Turn towards the target direction, starting from standing still turns instantly
+    let direction = if target_speed < EPSILON {
+        current.normalize_or_zero()
+    } else if speed < EPSILON {
+        target / target_speed
+    } else {
+        let current_direction = current / speed;
+        let target_direction = target / target_speed;
+        let angle = current_direction
+            .cross(target_direction)
+            .y
+            .atan2(current_direction.dot(target_direction));
+        let turn = angle.clamp(-movable.turn_rate * delta, movable.turn_rate * delta);
+        Quat::from_rotation_y(turn) * current_direction
+    };
+
+    // Accelerate when speeding up, use friction when slowing down
+    let new_speed = if target_speed > speed {
+        (speed + movable.acceleration * delta).min(target_speed)
+    } else {
+        (speed - movable.friction * delta).max(target_speed)
+    };
+
+    direction * new_speed
+}
+
+// Let the animations know when characters jump and fall
+pub fn sync_animation_parameters(
+    mut query: Query<(&CharacterController, &mut AnimationStateMachine)>,
+) {
+    for (controller, mut state_machine) in &mut query {
+        state_machine.parameters.grounded = controller.grounded;
+        state_machine.parameters.vertical_speed = controller.vertical_velocity;
+    }
+}
+
+pub fn spawn_blob_shadows(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut BlobShadow), Added<BlobShadow>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material: Local<Option<Handle<StandardMaterial>>>,
+) {
+    for (entity, mut blob_shadow) in &mut query {
+        let material = material
+            .get_or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: Color::rgba(0.0, 0.0, 0.0, 0.4),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                })
+            })
+            .clone();
+        let shadow = commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Circle::new(blob_shadow.radius))),
+                material,
+                transform: Transform::from_rotation(Quat::from_rotation_x(-PI * 0.5)),
+                ..default()
+            })
+            .insert(Name::new("Blob Shadow"))
+            .id();
+        commands.entity(entity).add_child(shadow);
+        blob_shadow.entity = Some(shadow);
+    }
+}
+
+// Keep shadows on the ground below their character, shrinking as it gets higher
+pub fn place_blob_shadows(
+    characters: Query<(&Transform, &CharacterController, &BlobShadow)>,
+    mut shadows: Query<
+        (&mut Transform, &mut Visibility),
+        (Without<CharacterController>, Without<BoxCollider>),
+    >,
+    colliders: Query<(&BoxCollider, &Transform), Without<CharacterController>>,
+) {
+    let obstacles: Vec<Obstacle> = colliders
+        .iter()
+        .map(|(collider, transform)| collider.obstacle(transform))
+        .collect();
+
+    for (transform, controller, blob_shadow) in &characters {
+        let Some(Ok((mut shadow_transform, mut visibility))) =
+            blob_shadow.entity.map(|entity| shadows.get_mut(entity))
+        else {
+            continue;
+        };
+        let feet = transform.translation + Vec3::Y * controller.feet_offset;
+        let hit = cast_ray(
+            feet + Vec3::Y * 0.1,
+            Vec3::NEG_Y,
+            blob_shadow.max_height,
+            &obstacles,
+        );
+
+        let Some(hit) = hit else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+
+        // Just above the ground, to not flicker with it
+        let height = (feet.y - hit.point.y).max(0.0);
+        shadow_transform.translation.y = hit.point.y + 0.01 - transform.translation.y;
+        shadow_transform.scale = Vec3::splat(1.0 - 0.6 * height / blob_shadow.max_height);
+    }
 }
 
 // This removes the Y-component of the vectors, so the directions are flat to the ground.
@@ -346,3 +633,147 @@ fn get_flat_camera_forward_and_right(camera_transform: &Transform) -> (Vec3, Vec
 
     (forward, right)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speed_after(movable: &Movable, mut current: Vec3, target: Vec3, seconds: f32) -> Vec3 {
+        for _ in 0..(seconds * 60.0) as usize {
+            current = step_velocity(movable, current, target, 1.0 / 60.0);
+        }
+        current
+    }
+
+    fn jumps_within(jump: &mut Jump, grounded: &[bool]) -> bool {
+        let mut controller = CharacterController::default();
+        for &grounded in grounded {
+            controller.grounded = grounded;
+            step_jump(jump, &mut controller, 0.05);
+            if controller.vertical_velocity > 0.0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn jumps_shortly_after_leaving_the_ground() {
+        let mut jump = Jump::default();
+        jumps_within(&mut jump, &[true]);
+        jump.requested = true;
+        assert!(jumps_within(&mut jump, &[false]));
+
+        let mut jump = Jump::default();
+        jumps_within(&mut jump, &[true, false, false, false]);
+        jump.requested = true;
+        assert!(!jumps_within(&mut jump, &[false]));
+    }
+
+    #[test]
+    fn jump_pressed_just_before_landing_is_remembered() {
+        let mut jump = Jump {
+            requested: true,
+            ..default()
+        };
+        assert!(jumps_within(&mut jump, &[false, false, true]));
+
+        let mut jump = Jump {
+            requested: true,
+            ..default()
+        };
+        assert!(!jumps_within(
+            &mut jump,
+            &[false, false, false, false, true]
+        ));
+    }
+
+    #[test]
+    fn one_press_only_jumps_once() {
+        let mut jump = Jump {
+            requested: true,
+            ..default()
+        };
+        assert!(jumps_within(&mut jump, &[true]));
+        assert!(!jumps_within(&mut jump, &[true, true]));
+    }
+
+    #[test]
+    fn pushing_the_stick_further_goes_from_walking_to_running() {
+        let movable = Movable::default();
+
+        assert_eq!(analog_speed(&movable, 0.25), movable.walk_speed * 0.5);
+        assert_eq!(analog_speed(&movable, 0.5), movable.walk_speed);
+        assert_eq!(analog_speed(&movable, 1.0), movable.run_speed);
+        assert!(analog_speed(&movable, 0.75) > movable.walk_speed);
+        assert!(analog_speed(&movable, 0.75) < movable.run_speed);
+    }
+
+    #[test]
+    fn run_animation_starts_between_walking_and_running() {
+        let villager = Movable {
+            walk_speed: 1.5,
+            run_speed: 4.0,
+            ..default()
+        };
+        let mut state_machine = CharacterBundle::new(villager.clone()).state_machine;
+
+        state_machine.parameters.speed = villager.walk_speed;
+        assert!(
+            state_machine.next_state(AnimationState::Idle, false) == Some(AnimationState::Walk)
+        );
+        state_machine.parameters.speed = villager.run_speed;
+        assert!(state_machine.next_state(AnimationState::Walk, false) == Some(AnimationState::Run));
+        state_machine.parameters.speed = 3.0;
+        assert!(state_machine.next_state(AnimationState::Walk, false) == Some(AnimationState::Run));
+    }
+
+    #[test]
+    fn accelerates_up_to_the_target_velocity() {
+        let movable = Movable::default();
+        let target = Vec3::X * movable.run_speed;
+
+        let started = step_velocity(&movable, Vec3::ZERO, target, 0.1);
+        assert!((started.length() - movable.acceleration * 0.1).abs() < 1e-4);
+        assert!(speed_after(&movable, Vec3::ZERO, target, 1.0).abs_diff_eq(target, 1e-4));
+    }
+
+    #[test]
+    fn friction_brings_the_character_to_a_stop() {
+        let movable = Movable::default();
+        let moving = Vec3::Z * movable.walk_speed;
+
+        let slowed = step_velocity(&movable, moving, Vec3::ZERO, 0.05);
+        assert!(slowed.length() < moving.length());
+        assert!(slowed.normalize().abs_diff_eq(Vec3::Z, 1e-4));
+        assert_eq!(speed_after(&movable, moving, Vec3::ZERO, 1.0), Vec3::ZERO);
+    }
+
+    #[test]
+    fn slows_down_in_time_to_stop_on_arrival() {
+        let movable = Movable::default();
+        let (mut position, mut velocity) = (0.0_f32, Vec3::ZERO);
+        for _ in 0..120 {
+            let distance = (1.0 - position).max(0.0);
+            let target = Vec3::X * movable.arrival_speed(movable.run_speed, distance);
+            velocity = step_velocity(&movable, velocity, target, 1.0 / 60.0);
+            position += velocity.x / 60.0;
+        }
+        assert!((position - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn turning_is_limited_by_the_turn_rate() {
+        let movable = Movable {
+            turn_rate: PI,
+            ..default()
+        };
+        let moving = Vec3::X * movable.walk_speed;
+        let target = Vec3::Z * movable.walk_speed;
+
+        let turned = step_velocity(&movable, moving, target, 0.25);
+        let angle = turned.angle_between(moving);
+        assert!((angle - PI * 0.25).abs() < 1e-4);
+        assert!(speed_after(&movable, moving, target, 1.0).abs_diff_eq(target, 1e-4));
+    }
+}
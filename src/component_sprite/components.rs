@@ -1,4 +1,55 @@
 use bevy::prelude::*;
+use serde::Deserialize;
 
+// Layers are stacked in the order they are declared, the first one furthest back
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Deserialize)]
+pub enum SpriteLayer {
+    Body,
+    Clothes,
+    Hair,
+    HeldItem,
+}
+
+pub struct ComponentSpriteLayer {
+    pub layer: SpriteLayer,
+    // Cut into frames the same way as the character's own sprite sheet
+    pub texture: Handle<Image>,
+}
+
+// A character built from stacked layers on top of its own AtlasSprite3d,
+// so new NPCs can mix and match parts instead of needing a new spritesheet.
+// Changing the layers rebuilds them
 #[derive(Component)]
-struct ComponentSprite {}
+pub struct ComponentSprite {
+    pub layers: Vec<ComponentSpriteLayer>,
+    // The character's own atlas, giving the frame layout of every layer
+    pub atlas: Handle<TextureAtlas>,
+    // Same as the character's own AtlasSprite3d, so the layers line up with it
+    pub pixels_per_metre: f32,
+    pub pivot: Vec2,
+    // Distance between layers, to keep them from z-fighting
+    pub layer_spacing: f32,
+}
+
+impl ComponentSprite {
+    pub fn new(
+        layers: Vec<ComponentSpriteLayer>,
+        atlas: Handle<TextureAtlas>,
+        pixels_per_metre: f32,
+        pivot: Vec2,
+    ) -> Self {
+        Self {
+            layers,
+            atlas,
+            pixels_per_metre,
+            pivot,
+            layer_spacing: 0.001,
+        }
+    }
+}
+
+// Child quad showing one layer of its parent's ComponentSprite
+#[derive(Component, Reflect)]
+pub struct ComponentSpritePart {
+    pub layer: SpriteLayer,
+}
@@ -1,19 +1,111 @@
 use bevy::prelude::*;
 
-mod components;
+pub mod components;
 mod systems;
+
+use components::*;
 use systems::*;
 
+use crate::animation::systems::animate_sprite_system;
 use crate::GameState;
 
 pub struct ComponentSpritePlugin;
 
 impl Plugin for ComponentSpritePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            (build_component_sprite, load_assets, setup)
-                .chain()
-                .in_schedule(OnEnter(GameState::Playing)),
+        app
+            // Register types
+            .register_type::<ComponentSpritePart>()
+            // On update
+            .add_systems(
+                (build_component_sprite, sync_component_sprite_frames)
+                    .chain()
+                    .after(animate_sprite_system)
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_sprite3d::{AtlasSprite3dComponent, Sprite3dPlugin};
+
+    use super::*;
+    use crate::testing::headless_app;
+
+    fn test_app() -> App {
+        let mut app = headless_app();
+        app.add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_asset::<TextureAtlas>()
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_plugin(Sprite3dPlugin)
+            .add_systems((build_component_sprite, sync_component_sprite_frames).chain());
+        app
+    }
+
+    fn layers(app: &App, entity: Entity) -> Vec<SpriteLayer> {
+        let mut layers: Vec<SpriteLayer> = app
+            .world
+            .get::<Children>(entity)
+            .into_iter()
+            .flatten()
+            .filter_map(|&child| app.world.get::<ComponentSpritePart>(child))
+            .map(|part| part.layer)
+            .collect();
+        layers.sort();
+        layers
+    }
+
+    #[test]
+    fn layers_are_rebuilt_when_changed() {
+        let mut app = test_app();
+        let mut images = app.world.resource_mut::<Assets<Image>>();
+        let body = images.add(Image::default());
+        let hat = images.add(Image::default());
+        let mut atlas = TextureAtlas::new_empty(body.clone(), Vec2::new(2.0, 1.0));
+        atlas.add_texture(Rect::new(0.0, 0.0, 1.0, 1.0));
+        atlas.add_texture(Rect::new(1.0, 0.0, 2.0, 1.0));
+        let atlas = app.world.resource_mut::<Assets<TextureAtlas>>().add(atlas);
+
+        let character = app
+            .world
+            .spawn((
+                ComponentSprite::new(
+                    vec![ComponentSpriteLayer {
+                        layer: SpriteLayer::Clothes,
+                        texture: body,
+                    }],
+                    atlas,
+                    28.0,
+                    Vec2::new(0.5, 0.0),
+                ),
+                AtlasSprite3dComponent {
+                    index: 1,
+                    atlas: Vec::new(),
+                },
+            ))
+            .id();
+        app.update();
+        assert_eq!(layers(&app, character), vec![SpriteLayer::Clothes]);
+
+        app.world
+            .get_mut::<ComponentSprite>(character)
+            .unwrap()
+            .layers
+            .push(ComponentSpriteLayer {
+                layer: SpriteLayer::Hair,
+                texture: hat,
+            });
+        app.update();
+        assert_eq!(
+            layers(&app, character),
+            vec![SpriteLayer::Clothes, SpriteLayer::Hair]
         );
+
+        app.world.entity_mut(character).remove::<ComponentSprite>();
+        app.update();
+        assert!(layers(&app, character).is_empty());
     }
 }
@@ -1,52 +1,124 @@
 use bevy::prelude::*;
-use bevy_mod_aseprite::*;
+use bevy::utils::HashMap;
+use bevy_sprite3d::{AtlasSprite3d, AtlasSprite3dComponent, Sprite3dParams};
 
-pub fn build_component_sprite(commands: Commands) {
-    println!("build_component_sprite");
-}
+use super::components::*;
 
-pub fn load_assets(asset_server: Res<AssetServer>, mut aseprite_handles: ResMut<AsepriteHandles>) {
-    let character: Handle<Aseprite> = asset_server.load("Character.aseprite");
-    aseprite_handles.push(character);
-}
+// Atlases cut from each layer texture, by the character atlas giving their layout
+type LayerAtlases = HashMap<(Handle<TextureAtlas>, Handle<Image>), Handle<TextureAtlas>>;
 
-pub fn setup(
+// (Re)build the child quads whenever the layers of a ComponentSprite change,
+// waiting until the textures of every layer are loaded
+pub fn build_component_sprite(
     mut commands: Commands,
-    aseprite_handles: Res<AsepriteHandles>,
-    aseprites: Res<Assets<Aseprite>>,
+    mut removed: RemovedComponents<ComponentSprite>,
+    query: Query<(Entity, Ref<ComponentSprite>, &AtlasSprite3dComponent)>,
+    part_query: Query<(Entity, &Parent), With<ComponentSpritePart>>,
+    mut pending: Local<Vec<Entity>>,
+    mut layer_atlases: Local<LayerAtlases>,
+    mut assets: ParamSet<(ResMut<Assets<TextureAtlas>>, Sprite3dParams)>,
 ) {
-    let aseprite_handle = &aseprite_handles[0];
-    let aseprite = aseprites.get(aseprite_handle).unwrap();
-    let animation = AsepriteAnimation::new(aseprite.info(), "idle");
+    let despawn_parts = |commands: &mut Commands, entity: Entity| {
+        for (part, parent) in &part_query {
+            if parent.get() == entity {
+                commands.entity(part).despawn_recursive();
+            }
+        }
+    };
+    for entity in removed.iter() {
+        despawn_parts(&mut commands, entity);
+    }
+
+    for (entity, component_sprite, _) in &query {
+        if component_sprite.is_changed() && !pending.contains(&entity) {
+            pending.push(entity);
+        }
+    }
+    let mut waiting = Vec::new();
+    for entity in pending.drain(..) {
+        let Ok((_, component_sprite, atlas_sprite)) = query.get(entity) else {
+            continue;
+        };
+        let sprite_params = assets.p1();
+        let loaded = sprite_params.atlases.contains(&component_sprite.atlas)
+            && component_sprite
+                .layers
+                .iter()
+                .all(|layer| sprite_params.images.contains(&layer.texture));
+        if !loaded {
+            waiting.push(entity);
+            continue;
+        }
 
-    commands.spawn(Player).insert(AsepriteBundle {
-        texture_atlas: aseprite.atlas().clone_weak(),
-        sprite: TextureAtlasSprite::new(animation.current_frame()),
-        aseprite: aseprite_handle.clone_weak(),
-        animation,
-        ..default()
-    });
+        // Cut each layer's texture into the same frames as the character's own
+        let mut layers: Vec<&ComponentSpriteLayer> = component_sprite.layers.iter().collect();
+        layers.sort_by_key(|layer| layer.layer);
+        let mut atlases = assets.p0();
+        let parts: Vec<(SpriteLayer, Handle<TextureAtlas>)> = layers
+            .iter()
+            .filter_map(|layer| {
+                let key = (
+                    component_sprite.atlas.clone_weak(),
+                    layer.texture.clone_weak(),
+                );
+                if let Some(atlas) = layer_atlases.get(&key) {
+                    return Some((layer.layer, atlas.clone()));
+                }
+                let layout = atlases.get(&component_sprite.atlas)?;
+                let mut atlas = TextureAtlas::new_empty(layer.texture.clone(), layout.size);
+                for &rect in layout.textures.iter() {
+                    atlas.add_texture(rect);
+                }
+                let atlas = atlases.add(atlas);
+                layer_atlases.insert(key, atlas.clone());
+                Some((layer.layer, atlas))
+            })
+            .collect();
+
+        despawn_parts(&mut commands, entity);
+        let mut sprite_params = assets.p1();
+        for (i, (layer, atlas)) in parts.into_iter().enumerate() {
+            let part = commands
+                .spawn(
+                    AtlasSprite3d {
+                        atlas,
+                        pixels_per_metre: component_sprite.pixels_per_metre,
+                        partial_alpha: true,
+                        unlit: false,
+                        index: atlas_sprite.index,
+                        pivot: Some(component_sprite.pivot),
+                        transform: Transform::from_xyz(
+                            0.0,
+                            0.0,
+                            (i + 1) as f32 * component_sprite.layer_spacing,
+                        ),
+                        ..default()
+                    }
+                    .bundle(&mut sprite_params),
+                )
+                .insert(ComponentSpritePart { layer })
+                .insert(Name::new(format!("{:?} Layer", layer)))
+                .id();
+            commands.entity(entity).add_child(part);
+        }
+    }
+    *pending = waiting;
 }
 
-fn transition_player(
-    time: Res<Time>,
-    player_q: Query<(&PlayerState, &Handle<Aseprite>, &AsepriteAnimation), With<Player>>,
-    aseprites: Res<Assets<Aseprite>>,
-    mut ev_player_changed: EventWriter<PlayerChanged>,
+// Keep every layer on the same frame as the parent's animation
+pub fn sync_component_sprite_frames(
+    parent_query: Query<
+        &AtlasSprite3dComponent,
+        (With<ComponentSprite>, Without<ComponentSpritePart>),
+    >,
+    mut part_query: Query<(&Parent, &mut AtlasSprite3dComponent), With<ComponentSpritePart>>,
 ) {
-    let (&player_state, handle, anim) = player_q.single();
-    let aseprite = aseprites.get(handle).unwrap();
-    match player_state {
-        PlayerState::Attack => {
-            let remaining_frames = anim.remaining_tag_frames(aseprite.info()).unwrap();
-            let frame_finished = anim.frame_finished(time.delta());
-            if remaining_frames == 0 && frame_finished {
-                ev_player_changed.send(PlayerChanged::default().new_state(PlayerState::Stand));
-            }
+    for (parent, mut part_sprite) in &mut part_query {
+        let Ok(parent_sprite) = parent_query.get(parent.get()) else {
+            continue;
+        };
+        if part_sprite.index != parent_sprite.index {
+            part_sprite.index = parent_sprite.index;
         }
-        _ => (),
     }
 }
-
-#[derive(Resource, Deref, DerefMut, Default)]
-struct AsepriteHandles(Vec<Handle<Aseprite>>);
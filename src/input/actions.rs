@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+// Everything the player can do, independent of which keys or buttons do it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    // Analog movement from -1 to 1, where pushing further goes from walking to running
+    MoveX,
+    MoveY,
+    Sprint,
+    Jump,
+    Attack,
+    // Talk to or use the closest interactable character
+    Interact,
+    // Sit down or stand back up
+    Sit,
+    // Walk to the ground under the cursor, in click to move mode
+    MoveToCursor,
+    // Switch between moving with the keys and click to move
+    ToggleControlMode,
+    // Switch between walking around obstacles and straight to the clicked spot
+    TogglePathfinding,
+    // Held to orbit the camera with the mouse
+    CameraOrbit,
+    // Analog orbiting, in either direction
+    CameraOrbitX,
+    CameraOrbitY,
+    // Positive zooms in, by how far the wheel was scrolled
+    CameraZoom,
+    // Positive zooms in, for as long as it is held
+    CameraZoomAxis,
+    // Turn a fixed angle camera a step around the player
+    CameraRotateLeft,
+    CameraRotateRight,
+    // Go through the follow, fixed angle and free flying cameras
+    CycleCameraMode,
+    // Point the camera at the closest character to talk to, or back at the player
+    LookAtCharacter,
+    AtmospherePreset(u8),
+    AtmosphereReset,
+    QuickSave,
+    QuickLoad,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // On any connected gamepad. Triggers are analog, from 0 to 1
+    GamepadButton(GamepadButtonType),
+    // From -1 to 1, on any connected gamepad
+    GamepadAxis(GamepadAxisType),
+    // Scrolled distance this frame, positive when scrolling up
+    MouseWheel,
+    // Only active while all of these are held, e.g. shift + right mouse
+    Chord(Vec<Binding>),
+    // Negates the value, e.g. to zoom out with the other trigger
+    Inverted(Box<Binding>),
+}
+
+// Which bindings trigger which action. Any of an action's bindings can trigger it
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    // How far sticks have to be pushed before they do anything, from 0 to 1
+    #[serde(default = "default_stick_dead_zone")]
+    pub stick_dead_zone: f32,
+    #[serde(default = "default_trigger_dead_zone")]
+    pub trigger_dead_zone: f32,
+}
+
+fn default_stick_dead_zone() -> f32 {
+    0.15
+}
+
+fn default_trigger_dead_zone() -> f32 {
+    0.05
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::*;
+
+        let mut bindings = BTreeMap::from([
+            (
+                Action::MoveForward,
+                vec![Key(KeyCode::W), GamepadButton(GamepadButtonType::DPadUp)],
+            ),
+            (
+                Action::MoveBackward,
+                vec![Key(KeyCode::S), GamepadButton(GamepadButtonType::DPadDown)],
+            ),
+            (
+                Action::MoveLeft,
+                vec![Key(KeyCode::A), GamepadButton(GamepadButtonType::DPadLeft)],
+            ),
+            (
+                Action::MoveRight,
+                vec![Key(KeyCode::D), GamepadButton(GamepadButtonType::DPadRight)],
+            ),
+            (
+                Action::MoveX,
+                vec![GamepadAxis(GamepadAxisType::LeftStickX)],
+            ),
+            (
+                Action::MoveY,
+                vec![GamepadAxis(GamepadAxisType::LeftStickY)],
+            ),
+            (
+                Action::Sprint,
+                vec![
+                    Key(KeyCode::LShift),
+                    Key(KeyCode::RShift),
+                    GamepadButton(GamepadButtonType::LeftThumb),
+                ],
+            ),
+            (
+                Action::Jump,
+                vec![Key(KeyCode::Space), GamepadButton(GamepadButtonType::South)],
+            ),
+            (
+                Action::Attack,
+                vec![Key(KeyCode::R), GamepadButton(GamepadButtonType::West)],
+            ),
+            (
+                Action::Interact,
+                vec![Key(KeyCode::T), GamepadButton(GamepadButtonType::East)],
+            ),
+            (
+                Action::Sit,
+                vec![
+                    Key(KeyCode::C),
+                    GamepadButton(GamepadButtonType::RightThumb),
+                ],
+            ),
+            (Action::MoveToCursor, vec![Mouse(MouseButton::Left)]),
+            (Action::ToggleControlMode, vec![Key(KeyCode::Tab)]),
+            (Action::TogglePathfinding, vec![Key(KeyCode::P)]),
+            (
+                Action::CameraOrbit,
+                vec![
+                    Mouse(MouseButton::Middle),
+                    Chord(vec![Key(KeyCode::LShift), Mouse(MouseButton::Right)]),
+                    Chord(vec![Key(KeyCode::RShift), Mouse(MouseButton::Right)]),
+                ],
+            ),
+            (
+                Action::CameraOrbitX,
+                vec![GamepadAxis(GamepadAxisType::RightStickX)],
+            ),
+            (
+                Action::CameraOrbitY,
+                vec![GamepadAxis(GamepadAxisType::RightStickY)],
+            ),
+            (Action::CameraZoom, vec![MouseWheel]),
+            (
+                Action::CameraZoomAxis,
+                vec![
+                    GamepadButton(GamepadButtonType::RightTrigger2),
+                    Inverted(Box::new(GamepadButton(GamepadButtonType::LeftTrigger2))),
+                ],
+            ),
+            (
+                Action::CameraRotateLeft,
+                vec![
+                    Key(KeyCode::Q),
+                    GamepadButton(GamepadButtonType::LeftTrigger),
+                ],
+            ),
+            (
+                Action::CameraRotateRight,
+                vec![
+                    Key(KeyCode::E),
+                    GamepadButton(GamepadButtonType::RightTrigger),
+                ],
+            ),
+            (
+                Action::CycleCameraMode,
+                vec![Key(KeyCode::F2), GamepadButton(GamepadButtonType::Select)],
+            ),
+            (
+                Action::LookAtCharacter,
+                vec![Key(KeyCode::F), GamepadButton(GamepadButtonType::North)],
+            ),
+            (Action::AtmosphereReset, vec![Key(KeyCode::Key0)]),
+            (Action::QuickSave, vec![Key(KeyCode::F5)]),
+            (Action::QuickLoad, vec![Key(KeyCode::F9)]),
+        ]);
+        let preset_keys = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        for (preset, key) in (1..).zip(preset_keys) {
+            bindings.insert(Action::AtmospherePreset(preset), vec![Key(key)]);
+        }
+
+        Self {
+            bindings,
+            stick_dead_zone: default_stick_dead_zone(),
+            trigger_dead_zone: default_trigger_dead_zone(),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |bindings| bindings)
+    }
+
+    // Add another binding for an action, keeping the existing ones
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    // Replace all bindings of an action
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: &Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|existing| existing != binding);
+        }
+    }
+
+    // Actions missing from the file, e.g. added after it was saved, get their default bindings
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let mut input_map: InputMap = ron::de::from_str(&text)?;
+        for (action, bindings) in InputMap::default().bindings {
+            input_map.bindings.entry(action).or_insert(bindings);
+        }
+        Ok(input_map)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+// How much each action is triggered this frame, updated from the InputMap before Update
+#[derive(Resource, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    previous: HashMap<Action, f32>,
+}
+
+impl ActionState {
+    // Buttons are 0 or 1, triggers 0 to 1, sticks -1 to 1,
+    // and the mouse wheel can be any amount in either direction
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        is_pressed(self.value(action))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && !is_pressed(self.previous.get(&action).copied().unwrap_or(0.0))
+    }
+
+    // Start a new frame, remembering the old values for just_pressed
+    pub fn swap(&mut self) {
+        self.previous = std::mem::take(&mut self.values);
+    }
+
+    pub fn set(&mut self, action: Action, value: f32) {
+        self.values.insert(action, value);
+    }
+}
+
+fn is_pressed(value: f32) -> bool {
+    value.abs() >= 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_map_round_trips_through_ron() {
+        let mut input_map = InputMap::default();
+        input_map.bind(Action::MoveForward, Binding::Key(KeyCode::Up));
+        input_map.bind(
+            Action::Sprint,
+            Binding::GamepadButton(GamepadButtonType::LeftThumb),
+        );
+
+        let text =
+            ron::ser::to_string_pretty(&input_map, ron::ser::PrettyConfig::default()).unwrap();
+        let loaded: InputMap = ron::de::from_str(&text).unwrap();
+        assert_eq!(loaded, input_map);
+    }
+
+    #[test]
+    fn rebinding_replaces_and_unbinding_removes() {
+        let mut input_map = InputMap::default();
+        input_map.rebind(Action::MoveForward, vec![Binding::Key(KeyCode::Z)]);
+        assert_eq!(
+            input_map.bindings(Action::MoveForward),
+            &[Binding::Key(KeyCode::Z)]
+        );
+
+        input_map.unbind(Action::MoveForward, &Binding::Key(KeyCode::Z));
+        assert!(input_map.bindings(Action::MoveForward).is_empty());
+    }
+
+    #[test]
+    fn just_pressed_only_on_the_first_frame() {
+        let mut actions = ActionState::default();
+        actions.set(Action::Sprint, 1.0);
+        assert!(actions.just_pressed(Action::Sprint));
+
+        actions.swap();
+        actions.set(Action::Sprint, 1.0);
+        assert!(actions.pressed(Action::Sprint));
+        assert!(!actions.just_pressed(Action::Sprint));
+    }
+}
@@ -0,0 +1,28 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+pub mod actions;
+mod systems;
+
+use actions::*;
+use systems::*;
+
+// Bindings are read from here at startup, and written back when rebound
+pub const INPUT_MAP_PATH: &str = "config/input.ron";
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActionState>()
+            // On startup
+            .add_startup_system(load_input_map)
+            // Before the game systems, once the raw input is updated
+            .add_system(
+                update_action_state
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(save_input_map.run_if(resource_exists::<InputMap>()));
+    }
+}
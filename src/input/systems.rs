@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+
+use super::actions::*;
+use super::INPUT_MAP_PATH;
+
+pub fn load_input_map(mut commands: Commands) {
+    let input_map = if Path::new(INPUT_MAP_PATH).exists() {
+        InputMap::load(INPUT_MAP_PATH).unwrap_or_else(|error| {
+            warn!(
+                "Using the default input bindings, could not load '{}': {}",
+                INPUT_MAP_PATH, error
+            );
+            InputMap::default()
+        })
+    } else {
+        // Write out the defaults, so there is a file to edit
+        let input_map = InputMap::default();
+        save(&input_map);
+        input_map
+    };
+    commands.insert_resource(input_map);
+}
+
+// Write the bindings back to the config file whenever they are rebound
+pub fn save_input_map(input_map: Res<InputMap>) {
+    if input_map.is_changed() && !input_map.is_added() {
+        save(&input_map);
+    }
+}
+
+fn save(input_map: &InputMap) {
+    if let Err(error) = input_map.save(INPUT_MAP_PATH) {
+        warn!(
+            "Could not save the input bindings to '{}': {}",
+            INPUT_MAP_PATH, error
+        );
+    }
+}
+
+pub fn update_action_state(
+    mut actions: ResMut<ActionState>,
+    input_map: Option<Res<InputMap>>,
+    keyboard: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_button_axes: Res<Axis<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut scroll_evr: EventReader<MouseWheel>,
+) {
+    actions.swap();
+    let Some(input_map) = input_map else {
+        return;
+    };
+
+    let inputs = RawInputs {
+        keyboard: &keyboard,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_button_axes: &gamepad_button_axes,
+        gamepad_axes: &gamepad_axes,
+        gamepads: &gamepads,
+        scroll: scroll_evr.iter().map(|ev| ev.y).sum(),
+        stick_dead_zone: input_map.stick_dead_zone,
+        trigger_dead_zone: input_map.trigger_dead_zone,
+    };
+    for (&action, bindings) in input_map.bindings.iter() {
+        // The strongest binding wins
+        let value = strongest(bindings.iter().map(|binding| inputs.value(binding)));
+        actions.set(action, value);
+    }
+}
+
+struct RawInputs<'a> {
+    keyboard: &'a Input<KeyCode>,
+    mouse: &'a Input<MouseButton>,
+    gamepad_buttons: &'a Input<GamepadButton>,
+    gamepad_button_axes: &'a Axis<GamepadButton>,
+    gamepad_axes: &'a Axis<GamepadAxis>,
+    gamepads: &'a Gamepads,
+    scroll: f32,
+    stick_dead_zone: f32,
+    trigger_dead_zone: f32,
+}
+
+impl RawInputs<'_> {
+    fn value(&self, binding: &Binding) -> f32 {
+        let pressed = match binding {
+            Binding::Key(key) => self.keyboard.pressed(*key),
+            Binding::Mouse(button) => self.mouse.pressed(*button),
+            Binding::GamepadButton(button_type) => {
+                return strongest(
+                    self.gamepads.iter().map(|gamepad| {
+                        self.gamepad_button(GamepadButton::new(gamepad, *button_type))
+                    }),
+                )
+            }
+            Binding::GamepadAxis(axis_type) => {
+                return strongest(
+                    self.gamepads
+                        .iter()
+                        .map(|gamepad| self.gamepad_axis(gamepad, *axis_type)),
+                )
+            }
+            Binding::MouseWheel => return self.scroll,
+            Binding::Inverted(binding) => return -self.value(binding),
+            Binding::Chord(bindings) => bindings.iter().all(|binding| self.value(binding) != 0.0),
+        };
+        if pressed {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    // Analog buttons like the triggers report how far they are pressed, others 0 or 1
+    fn gamepad_button(&self, button: GamepadButton) -> f32 {
+        match self.gamepad_button_axes.get(button) {
+            Some(value) => apply_dead_zone(value.abs(), self.trigger_dead_zone),
+            None if self.gamepad_buttons.pressed(button) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    fn gamepad_axis(&self, gamepad: Gamepad, axis_type: GamepadAxisType) -> f32 {
+        let axis_value = |axis_type| {
+            self.gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        let value = axis_value(axis_type);
+
+        // The dead zone of sticks is round, so it needs both of their axes
+        let Some(other_axis_type) = other_stick_axis(axis_type) else {
+            return apply_dead_zone(value.abs(), self.trigger_dead_zone) * value.signum();
+        };
+        let stick = Vec2::new(value, axis_value(other_axis_type));
+        let tilt = stick.length();
+        if tilt <= 0.0 {
+            return 0.0;
+        }
+        value / tilt * apply_dead_zone(tilt.min(1.0), self.stick_dead_zone)
+    }
+}
+
+fn other_stick_axis(axis_type: GamepadAxisType) -> Option<GamepadAxisType> {
+    match axis_type {
+        GamepadAxisType::LeftStickX => Some(GamepadAxisType::LeftStickY),
+        GamepadAxisType::LeftStickY => Some(GamepadAxisType::LeftStickX),
+        GamepadAxisType::RightStickX => Some(GamepadAxisType::RightStickY),
+        GamepadAxisType::RightStickY => Some(GamepadAxisType::RightStickX),
+        _ => None,
+    }
+}
+
+// Ignore anything inside the dead zone, and rescale the rest to still go from 0 to 1
+pub fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value <= dead_zone {
+        0.0
+    } else {
+        ((value - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+}
+
+// Keeps the sign, so axes pointing either way can be combined
+fn strongest(values: impl Iterator<Item = f32>) -> f32 {
+    values.fold(0.0, |strongest: f32, value| {
+        if value.abs() > strongest.abs() {
+            value
+        } else {
+            strongest
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone_is_ignored_and_the_rest_rescaled() {
+        assert_eq!(apply_dead_zone(0.1, 0.2), 0.0);
+        assert_eq!(apply_dead_zone(0.2, 0.2), 0.0);
+        assert!((apply_dead_zone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert_eq!(apply_dead_zone(1.0, 0.2), 1.0);
+    }
+}
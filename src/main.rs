@@ -2,19 +2,34 @@ use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_atmosphere::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use bevy_mod_aseprite::AsepritePlugin;
 use bevy_sprite3d::Sprite3dPlugin;
 
 mod systems;
 use systems::*;
 
+pub mod ai;
 pub mod animation;
 mod camera;
 pub mod character;
 pub mod component_sprite;
+pub mod input;
+pub mod navigation;
+pub mod physics;
+pub mod save;
+#[cfg(test)]
+mod testing;
+use crate::ai::AiPlugin;
 use crate::animation::AnimationPlugin;
 use crate::camera::CameraPlugin;
+use crate::character::level::Level;
 use crate::character::PlayerPlugin;
 use crate::component_sprite::ComponentSpritePlugin;
+use crate::input::actions::{Action, ActionState};
+use crate::input::InputMapPlugin;
+use crate::navigation::NavigationPlugin;
+use crate::physics::PhysicsPlugin;
+use crate::save::SavePlugin;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 enum GameState {
@@ -23,12 +38,17 @@ enum GameState {
     Playing,
 }
 
+// The sky preset picked with the number keys, 0 is the default sky
+#[derive(Resource, Reflect, Default)]
+#[reflect(Resource)]
+pub struct Atmosphere {
+    pub preset: u8,
+}
+
 #[derive(AssetCollection, Resource)]
-pub struct ImageAssets {
-    #[asset(texture_atlas(tile_size_x = 20.0, tile_size_y = 28.0))]
-    #[asset(texture_atlas(columns = 4, rows = 9))]
-    #[asset(path = "Character.png")]
-    character_sheet: Handle<TextureAtlas>,
+pub struct LevelAssets {
+    #[asset(path = "Village.level.ron")]
+    level: Handle<Level>,
 }
 
 fn main() {
@@ -39,84 +59,141 @@ fn main() {
         .add_loading_state(
             LoadingState::new(GameState::Loading).continue_to_state(GameState::Playing),
         )
-        .add_collection_to_loading_state::<_, ImageAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, LevelAssets>(GameState::Loading)
         .insert_resource(ClearColor(Color::rgb(0.16, 0.16, 0.16)))
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                // Hot-reload assets, such as animation sets, when they change on disk
+                .set(AssetPlugin {
+                    watch_for_changes: true,
+                    ..default()
+                }),
+        )
         // Inspector
         .add_plugin(WorldInspectorPlugin::new())
         // Other plugins
         .add_plugin(Sprite3dPlugin)
+        .add_plugin(AsepritePlugin)
         // Our systems
+        .add_plugin(InputMapPlugin)
         .add_plugin(CameraPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(AnimationPlugin)
         .add_plugin(ComponentSpritePlugin)
+        .add_plugin(PhysicsPlugin)
+        .add_plugin(AiPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(SavePlugin)
         .add_startup_system(spawn_basic_scene)
-        .add_system(change_nishita)
+        .register_type::<Atmosphere>()
+        .init_resource::<Atmosphere>()
+        .add_system(change_nishita.before(apply_atmosphere))
+        .add_system(apply_atmosphere)
         .run();
 }
 
-fn change_nishita(mut commands: Commands, keys: Res<Input<KeyCode>>) {
-    if keys.just_pressed(KeyCode::Key1) {
-        info!("Changed to Atmosphere Preset 1 (Sunset)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            sun_position: Vec3::new(0., 1., -1.),
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key2) {
-        info!("Changed to Atmosphere Preset 2 (Noir Sunset)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            sun_position: Vec3::new(0., 1., -1.),
-            rayleigh_coefficient: Vec3::new(1e-5, 1e-5, 1e-5),
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key3) {
-        info!("Changed to Atmosphere Preset 3 (Magenta)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            rayleigh_coefficient: Vec3::new(2e-5, 1e-5, 2e-5),
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key4) {
-        info!("Changed to Atmosphere Preset 4 (Strong Mie)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            mie_coefficient: 5e-5,
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key5) {
-        info!("Changed to Atmosphere Preset 5 (Larger Scale)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            rayleigh_scale_height: 16e3,
-            mie_scale_height: 2.4e3,
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key6) {
-        info!("Changed to Atmosphere Preset 6 (Weak Intensity)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            sun_intensity: 11.0,
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key7) {
-        info!("Changed to Atmosphere Preset 7 (Half Radius)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            ray_origin: Vec3::new(0., 6372e3 / 2., 0.),
-            planet_radius: 6371e3 / 2.,
-            atmosphere_radius: 6471e3 / 2.,
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key8) {
-        info!("Changed to Atmosphere Preset 8 (Sideways World)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            ray_origin: Vec3::new(6372e3, 0., 0.),
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key9) {
-        info!("Changed to Atmosphere Preset 9 (Inverted Mie Direction)");
-        commands.insert_resource(AtmosphereModel::new(Nishita {
-            mie_direction: -0.758,
-            ..default()
-        }));
-    } else if keys.just_pressed(KeyCode::Key0) {
-        info!("Reset Atmosphere to Default");
-        commands.remove_resource::<AtmosphereModel>();
+// Pick a preset with the number keys, or go back to the default sky
+fn change_nishita(mut atmosphere: ResMut<Atmosphere>, actions: Res<ActionState>) {
+    for preset in 1..=9 {
+        if actions.just_pressed(Action::AtmospherePreset(preset)) {
+            atmosphere.preset = preset;
+        }
+    }
+    if actions.just_pressed(Action::AtmosphereReset) {
+        atmosphere.preset = 0;
+    }
+}
+
+// Set the sky whenever the preset changes, including when a save file is loaded
+fn apply_atmosphere(mut commands: Commands, atmosphere: Res<Atmosphere>) {
+    if !atmosphere.is_changed() || atmosphere.is_added() {
+        return;
+    }
+    match nishita_preset(atmosphere.preset) {
+        Some((name, nishita)) => {
+            info!(
+                "Changed to Atmosphere Preset {} ({})",
+                atmosphere.preset, name
+            );
+            commands.insert_resource(AtmosphereModel::new(nishita));
+        }
+        None => {
+            info!("Reset Atmosphere to Default");
+            commands.remove_resource::<AtmosphereModel>();
+        }
     }
 }
+
+fn nishita_preset(preset: u8) -> Option<(&'static str, Nishita)> {
+    Some(match preset {
+        1 => (
+            "Sunset",
+            Nishita {
+                sun_position: Vec3::new(0., 1., -1.),
+                ..default()
+            },
+        ),
+        2 => (
+            "Noir Sunset",
+            Nishita {
+                sun_position: Vec3::new(0., 1., -1.),
+                rayleigh_coefficient: Vec3::new(1e-5, 1e-5, 1e-5),
+                ..default()
+            },
+        ),
+        3 => (
+            "Magenta",
+            Nishita {
+                rayleigh_coefficient: Vec3::new(2e-5, 1e-5, 2e-5),
+                ..default()
+            },
+        ),
+        4 => (
+            "Strong Mie",
+            Nishita {
+                mie_coefficient: 5e-5,
+                ..default()
+            },
+        ),
+        5 => (
+            "Larger Scale",
+            Nishita {
+                rayleigh_scale_height: 16e3,
+                mie_scale_height: 2.4e3,
+                ..default()
+            },
+        ),
+        6 => (
+            "Weak Intensity",
+            Nishita {
+                sun_intensity: 11.0,
+                ..default()
+            },
+        ),
+        7 => (
+            "Half Radius",
+            Nishita {
+                ray_origin: Vec3::new(0., 6372e3 / 2., 0.),
+                planet_radius: 6371e3 / 2.,
+                atmosphere_radius: 6471e3 / 2.,
+                ..default()
+            },
+        ),
+        8 => (
+            "Sideways World",
+            Nishita {
+                ray_origin: Vec3::new(6372e3, 0., 0.),
+                ..default()
+            },
+        ),
+        9 => (
+            "Inverted Mie Direction",
+            Nishita {
+                mie_direction: -0.758,
+                ..default()
+            },
+        ),
+        _ => return None,
+    })
+}
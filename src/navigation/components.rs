@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+// Walks a character to `destination` along a path found on the NavGrid
+#[derive(Component, Reflect)]
+pub struct PathFollower {
+    // Set this to walk somewhere, it is cleared again on arrival
+    pub destination: Option<Vec3>,
+    pub run: bool,
+    // Path around obstacles on the NavGrid, or walk straight there
+    pub use_nav_grid: bool,
+    // How close to the destination counts as arrived
+    pub arrive_distance: f32,
+    // Other characters closer than this are steered away from
+    pub avoidance_radius: f32,
+    // Waypoints still to go, the last one is the destination
+    pub path: Vec<Vec3>,
+    // The destination the path was found for, to notice when it changes
+    pub planned_for: Option<Vec3>,
+}
+
+impl Default for PathFollower {
+    fn default() -> Self {
+        Self {
+            destination: None,
+            run: false,
+            use_nav_grid: true,
+            arrive_distance: 0.15,
+            avoidance_radius: 0.8,
+            path: Vec::new(),
+            planned_for: None,
+        }
+    }
+}
+
+impl PathFollower {
+    pub fn go_to(&mut self, destination: Vec3) {
+        self.destination = Some(destination);
+    }
+
+    pub fn stop(&mut self) {
+        self.destination = None;
+        self.path.clear();
+        self.planned_for = None;
+    }
+}
@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+use crate::physics::collision::{capsule_contact, cast_ray, Obstacle};
+use crate::physics::components::CharacterController;
+
+// Size of the characters the grid is built for
+#[derive(Resource, Clone)]
+pub struct NavGridSettings {
+    pub cell_size: f32,
+    pub agent_radius: f32,
+    pub agent_height: f32,
+    pub step_height: f32,
+    pub max_slope: f32,
+}
+
+impl Default for NavGridSettings {
+    fn default() -> Self {
+        let controller = CharacterController::default();
+        Self {
+            cell_size: 0.25,
+            agent_radius: controller.radius,
+            agent_height: controller.height,
+            step_height: controller.step_height,
+            max_slope: controller.max_slope,
+        }
+    }
+}
+
+// Walkable space of the scene, as a grid of cells seen from above
+#[derive(Resource, Clone)]
+pub struct NavGrid {
+    // Corner of the first cell, at the lowest x and z
+    pub origin: Vec2,
+    pub cell_size: f32,
+    pub width: usize,
+    pub depth: usize,
+    pub step_height: f32,
+    // Height of the ground in each cell, or None if characters can't stand there
+    cells: Vec<Option<f32>>,
+}
+
+pub type Cell = (usize, usize);
+
+impl NavGrid {
+    // Sample the obstacles from above, in the area they cover
+    pub fn build(settings: &NavGridSettings, obstacles: &[Obstacle]) -> Self {
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        for obstacle in obstacles {
+            if let Obstacle::Box {
+                center,
+                rotation,
+                half_extents,
+            } = *obstacle
+            {
+                // Bounding box of the rotated box
+                let matrix = Mat3::from_quat(rotation);
+                let extents = matrix.x_axis.abs() * half_extents.x
+                    + matrix.y_axis.abs() * half_extents.y
+                    + matrix.z_axis.abs() * half_extents.z;
+                min = min.min(center - extents);
+                max = max.max(center + extents);
+            }
+        }
+        if min.x > max.x {
+            return Self::empty(settings);
+        }
+
+        let cell_size = settings.cell_size;
+        let width = ((max.x - min.x) / cell_size).ceil() as usize;
+        let depth = ((max.z - min.z) / cell_size).ceil() as usize;
+        let mut grid = Self {
+            origin: Vec2::new(min.x, min.z),
+            cell_size,
+            width,
+            depth,
+            step_height: settings.step_height,
+            cells: vec![None; width * depth],
+        };
+
+        let agent = CharacterController {
+            radius: settings.agent_radius,
+            height: settings.agent_height,
+            feet_offset: 0.0,
+            step_height: settings.step_height,
+            max_slope: settings.max_slope,
+            ..default()
+        };
+        let min_walkable = settings.max_slope.cos();
+        for z in 0..depth {
+            for x in 0..width {
+                let center = grid.cell_center((x, z), 0.0);
+                let origin = Vec3::new(center.x, max.y + 1.0, center.z);
+                let Some(hit) = cast_ray(origin, Vec3::NEG_Y, max.y - min.y + 2.0, obstacles)
+                else {
+                    continue;
+                };
+                if hit.normal.y < min_walkable {
+                    continue;
+                }
+
+                // Make sure a character fits, standing just above the ground
+                let capsule = agent.capsule(hit.point + Vec3::Y * 0.05);
+                let feet = hit.point.y + 0.05;
+                let blocked = obstacles.iter().any(|obstacle| {
+                    capsule_contact(&capsule, obstacle).map_or(false, |contact| {
+                        let ground = contact.normal.y >= min_walkable;
+                        let step = contact.surface_normal.y >= min_walkable
+                            && contact.point.y - feet <= settings.step_height;
+                        !ground && !step
+                    })
+                });
+                if !blocked {
+                    grid.cells[z * width + x] = Some(hit.point.y);
+                }
+            }
+        }
+
+        grid
+    }
+
+    pub fn empty(settings: &NavGridSettings) -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            cell_size: settings.cell_size,
+            width: 0,
+            depth: 0,
+            step_height: settings.step_height,
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn cell_at(&self, position: Vec3) -> Option<Cell> {
+        let x = ((position.x - self.origin.x) / self.cell_size).floor();
+        let z = ((position.z - self.origin.y) / self.cell_size).floor();
+        if x < 0.0 || z < 0.0 || x as usize >= self.width || z as usize >= self.depth {
+            return None;
+        }
+        Some((x as usize, z as usize))
+    }
+
+    pub fn cell_center(&self, (x, z): Cell, height: f32) -> Vec3 {
+        Vec3::new(
+            self.origin.x + (x as f32 + 0.5) * self.cell_size,
+            height,
+            self.origin.y + (z as f32 + 0.5) * self.cell_size,
+        )
+    }
+
+    // Height of the ground, if the cell is walkable
+    pub fn ground_height(&self, (x, z): Cell) -> Option<f32> {
+        if x >= self.width || z >= self.depth {
+            return None;
+        }
+        self.cells[z * self.width + x]
+    }
+
+    pub fn is_walkable(&self, cell: Cell) -> bool {
+        self.ground_height(cell).is_some()
+    }
+
+    // Shortest path over the grid with A*, as waypoints on the ground after `start`, ending at
+    // `goal`. Positions off the grid or in blocked cells use the closest walkable cell instead
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start_cell = self.nearest_walkable(start)?;
+        let goal_cell = self.nearest_walkable(goal)?;
+        let goal_position = if self.cell_at(goal) == Some(goal_cell) {
+            Vec3::new(goal.x, self.ground_height(goal_cell)?, goal.z)
+        } else {
+            self.cell_center(goal_cell, self.ground_height(goal_cell)?)
+        };
+
+        let cells = self.search(start_cell, goal_cell)?;
+        let mut waypoints: Vec<Vec3> = cells
+            .iter()
+            .skip(1)
+            .map(|&cell| self.cell_center(cell, self.ground_height(cell).unwrap_or(0.0)))
+            .collect();
+        waypoints.pop();
+        waypoints.push(goal_position);
+
+        Some(self.smooth(start, waypoints))
+    }
+
+    fn search(&self, start: Cell, goal: Cell) -> Option<Vec<Cell>> {
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Cell, Cell> = HashMap::new();
+        let mut costs: HashMap<Cell, f32> = HashMap::from([(start, 0.0)]);
+        let mut closed: HashSet<Cell> = HashSet::new();
+        open.push(OpenCell {
+            cell: start,
+            estimate: self.distance(start, goal),
+        });
+
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            // Outdated entry for a cell that was reached more cheaply later
+            if !closed.insert(cell) {
+                continue;
+            }
+            if cell == goal {
+                let mut path = vec![cell];
+                let mut current = cell;
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(previous);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cost = costs[&cell];
+            for (neighbour, step_cost) in self.neighbours(cell) {
+                if closed.contains(&neighbour) {
+                    continue;
+                }
+                let new_cost = cost + step_cost;
+                if costs
+                    .get(&neighbour)
+                    .map_or(true, |&existing| new_cost < existing)
+                {
+                    costs.insert(neighbour, new_cost);
+                    came_from.insert(neighbour, cell);
+                    open.push(OpenCell {
+                        cell: neighbour,
+                        estimate: new_cost + self.distance(neighbour, goal),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    // Walkable cells around this one, and the cost to move there.
+    // Diagonals only when both cells next to them are open, to not cut corners
+    fn neighbours(&self, (x, z): Cell) -> Vec<(Cell, f32)> {
+        let mut neighbours = Vec::with_capacity(8);
+        for (dx, dz) in [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            let (Some(nx), Some(nz)) = (x.checked_add_signed(dx), z.checked_add_signed(dz)) else {
+                continue;
+            };
+            if !self.can_step((x, z), (nx, nz)) {
+                continue;
+            }
+            if dx != 0
+                && dz != 0
+                && !(self.can_step((x, z), (nx, z)) && self.can_step((x, z), (x, nz)))
+            {
+                continue;
+            }
+            let cost = if dx != 0 && dz != 0 {
+                std::f32::consts::SQRT_2
+            } else {
+                1.0
+            };
+            neighbours.push(((nx, nz), cost));
+        }
+        neighbours
+    }
+
+    // Both cells walkable, and no higher step between them than a character can climb
+    fn can_step(&self, from: Cell, to: Cell) -> bool {
+        match (self.ground_height(from), self.ground_height(to)) {
+            (Some(from), Some(to)) => (to - from).abs() <= self.step_height,
+            _ => false,
+        }
+    }
+
+    fn distance(&self, (ax, az): Cell, (bx, bz): Cell) -> f32 {
+        Vec2::new(ax as f32 - bx as f32, az as f32 - bz as f32).length()
+    }
+
+    fn nearest_walkable(&self, position: Vec3) -> Option<Cell> {
+        if let Some(cell) = self.cell_at(position) {
+            if self.is_walkable(cell) {
+                return Some(cell);
+            }
+        }
+        (0..self.depth)
+            .flat_map(|z| (0..self.width).map(move |x| (x, z)))
+            .filter(|&cell| self.is_walkable(cell))
+            .min_by(|&a, &b| {
+                let a = self.cell_center(a, position.y).distance_squared(position);
+                let b = self.cell_center(b, position.y).distance_squared(position);
+                a.total_cmp(&b)
+            })
+    }
+
+    // Skip waypoints that can be reached in a straight line
+    fn smooth(&self, start: Vec3, waypoints: Vec<Vec3>) -> Vec<Vec3> {
+        let mut smoothed = Vec::new();
+        let mut from = start;
+        let mut index = 0;
+        while index < waypoints.len() {
+            let furthest = ((index + 1)..waypoints.len())
+                .rev()
+                .find(|&candidate| self.has_line_of_sight(from, waypoints[candidate]))
+                .unwrap_or(index);
+            smoothed.push(waypoints[furthest]);
+            from = waypoints[furthest];
+            index = furthest + 1;
+        }
+        smoothed
+    }
+
+    // Walk the line in small steps, checking every cell along the way
+    pub fn has_line_of_sight(&self, from: Vec3, to: Vec3) -> bool {
+        let Some(mut previous) = self.cell_at(from) else {
+            return false;
+        };
+        let steps = (from.distance(to) / (self.cell_size * 0.25)).ceil() as usize;
+        for step in 1..=steps {
+            let Some(cell) = self.cell_at(from.lerp(to, step as f32 / steps as f32)) else {
+                return false;
+            };
+            if cell != previous {
+                // Diagonal moves need both cells next to them to be open, like in the search
+                let (px, pz) = previous;
+                let (x, z) = cell;
+                if !self.can_step(previous, cell)
+                    || (px != x
+                        && pz != z
+                        && !(self.can_step(previous, (x, pz)) && self.can_step(previous, (px, z))))
+                {
+                    return false;
+                }
+                previous = cell;
+            }
+        }
+        true
+    }
+}
+
+// Ordered so the BinaryHeap pops the lowest estimate first
+struct OpenCell {
+    cell: Cell,
+    estimate: f32,
+}
+
+impl PartialEq for OpenCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenCell {}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(center: Vec3, half_extents: Vec3) -> Obstacle {
+        Obstacle::Box {
+            center,
+            rotation: Quat::IDENTITY,
+            half_extents,
+        }
+    }
+
+    fn ground() -> Obstacle {
+        block(Vec3::new(0.0, -0.5, 0.0), Vec3::new(5.0, 0.5, 5.0))
+    }
+
+    // Wall across the middle, with a gap at the +z end
+    fn wall() -> Obstacle {
+        block(Vec3::new(0.0, 1.0, -1.0), Vec3::new(0.25, 1.0, 4.0))
+    }
+
+    fn path_length(start: Vec3, path: &[Vec3]) -> f32 {
+        let mut length = 0.0;
+        let mut from = start;
+        for &waypoint in path {
+            length += from.distance(waypoint);
+            from = waypoint;
+        }
+        length
+    }
+
+    #[test]
+    fn walls_and_their_surroundings_are_blocked() {
+        let grid = NavGrid::build(&NavGridSettings::default(), &[ground(), wall()]);
+
+        let cell = |x| grid.cell_at(Vec3::new(x, 0.0, 0.0)).unwrap();
+        assert_eq!(grid.ground_height(cell(-3.0)), Some(0.0));
+        // Standing on top of the wall, not beside it
+        assert_eq!(grid.ground_height(cell(0.0)), Some(2.0));
+        // Too close to the wall for a character to stand
+        assert!(!grid.is_walkable(cell(0.4)));
+        assert!(!grid.is_walkable(cell(-0.4)));
+    }
+
+    #[test]
+    fn paths_go_around_walls() {
+        let grid = NavGrid::build(&NavGridSettings::default(), &[ground(), wall()]);
+        let start = Vec3::new(-2.0, 0.0, -2.0);
+        let goal = Vec3::new(2.0, 0.0, -2.0);
+        let path = grid.find_path(start, goal).unwrap();
+
+        assert_eq!(*path.last().unwrap(), goal);
+        // Through the gap at the end of the wall
+        assert!(path.iter().any(|waypoint| waypoint.z > 3.0));
+        let mut from = start;
+        for &waypoint in path.iter() {
+            assert!(grid.has_line_of_sight(from, waypoint));
+            from = waypoint;
+        }
+    }
+
+    #[test]
+    fn straight_paths_are_a_single_waypoint() {
+        let grid = NavGrid::build(&NavGridSettings::default(), &[ground()]);
+        let goal = Vec3::new(3.0, 0.0, 1.0);
+        let path = grid.find_path(Vec3::new(-3.0, 0.0, -2.0), goal).unwrap();
+
+        assert_eq!(path, vec![goal]);
+    }
+
+    #[test]
+    fn paths_find_the_shorter_way_round() {
+        let grid = NavGrid::build(&NavGridSettings::default(), &[ground(), wall()]);
+        let start = Vec3::new(-1.0, 0.0, 2.5);
+        let path = grid.find_path(start, Vec3::new(1.0, 0.0, 2.5)).unwrap();
+
+        assert!(path_length(start, &path) < 4.0);
+    }
+
+    #[test]
+    fn low_steps_can_be_climbed_but_not_high_ledges() {
+        let step = block(Vec3::new(3.0, 0.1, 0.0), Vec3::new(1.0, 0.1, 1.0));
+        let ledge = block(Vec3::new(-3.0, 0.5, 0.0), Vec3::new(1.0, 0.5, 1.0));
+        let grid = NavGrid::build(&NavGridSettings::default(), &[ground(), step, ledge]);
+
+        let on_step = Vec3::new(3.0, 0.2, 0.0);
+        let path = grid.find_path(Vec3::ZERO, on_step).unwrap();
+        assert!(path.last().unwrap().abs_diff_eq(on_step, 1e-5));
+
+        // The top of the ledge is walkable, but can't be reached
+        assert!(grid.is_walkable(grid.cell_at(Vec3::new(-3.0, 1.0, 0.0)).unwrap()));
+        assert!(grid
+            .find_path(Vec3::ZERO, Vec3::new(-3.0, 1.0, 0.0))
+            .is_none());
+    }
+}
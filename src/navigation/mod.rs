@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+pub mod components;
+pub mod grid;
+mod systems;
+
+use components::*;
+use grid::*;
+use systems::*;
+
+use crate::character::MoveCharacterSet;
+use crate::GameState;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Register types
+            .register_type::<PathFollower>()
+            .init_resource::<NavGridSettings>()
+            // On update
+            .add_systems(
+                (build_nav_grid, apply_system_buffers, follow_paths)
+                    .chain()
+                    .before(MoveCharacterSet)
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character::components::{Movable, Velocity};
+    use crate::physics::components::BoxCollider;
+    use crate::testing::playing_app;
+
+    fn test_app() -> App {
+        let mut app = playing_app();
+        app.add_plugin(NavigationPlugin);
+        app.world.spawn((
+            BoxCollider {
+                half_extents: Vec3::new(5.0, 0.5, 5.0),
+            },
+            Transform::from_xyz(0.0, -0.5, 0.0),
+        ));
+        app
+    }
+
+    #[test]
+    fn paths_are_replanned_when_the_grid_changes() {
+        let mut app = test_app();
+        let destination = Vec3::new(2.0, 0.0, -2.0);
+        let character = app
+            .world
+            .spawn((
+                PathFollower {
+                    destination: Some(destination),
+                    ..default()
+                },
+                Movable::default(),
+                Velocity::default(),
+                Transform::from_xyz(-2.0, 0.0, -2.0),
+            ))
+            .id();
+
+        app.update();
+        app.update();
+        let follower = app.world.get::<PathFollower>(character).unwrap();
+        assert_eq!(follower.path, vec![destination]);
+
+        // A wall across the way, with a gap at the +z end
+        app.world.spawn((
+            BoxCollider {
+                half_extents: Vec3::new(0.25, 1.0, 4.0),
+            },
+            Transform::from_xyz(0.0, 1.0, -1.0),
+        ));
+        app.update();
+        let follower = app.world.get::<PathFollower>(character).unwrap();
+        assert!(follower.path.iter().any(|waypoint| waypoint.z > 3.0));
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+
+use super::components::*;
+use super::grid::*;
+use crate::character::components::{Movable, Velocity};
+use crate::physics::collision::Obstacle;
+use crate::physics::components::{BoxCollider, CharacterController};
+
+// Close enough to a waypoint on the way to move on to the next one
+const WAYPOINT_DISTANCE: f32 = 0.3;
+
+type ChangedColliders = (
+    With<BoxCollider>,
+    Or<(Changed<BoxCollider>, Changed<Transform>)>,
+);
+
+// Rebuild the grid whenever colliders are added, moved or removed
+pub fn build_nav_grid(
+    mut commands: Commands,
+    settings: Res<NavGridSettings>,
+    colliders: Query<(&BoxCollider, &Transform)>,
+    changed: Query<(), ChangedColliders>,
+    mut removed: RemovedComponents<BoxCollider>,
+) {
+    if changed.is_empty() && removed.iter().count() == 0 {
+        return;
+    }
+
+    let obstacles: Vec<Obstacle> = colliders
+        .iter()
+        .map(|(collider, transform)| collider.obstacle(transform))
+        .collect();
+    commands.insert_resource(NavGrid::build(&settings, &obstacles));
+}
+
+pub fn follow_paths(
+    mut followers: Query<(
+        Entity,
+        &mut PathFollower,
+        &Movable,
+        &Transform,
+        &mut Velocity,
+    )>,
+    characters: Query<(Entity, &Transform), With<CharacterController>>,
+    grid: Option<Res<NavGrid>>,
+) {
+    // Paths planned over an old grid may lead through cells that are blocked now
+    let grid_changed = grid.as_ref().map_or(false, |grid| grid.is_changed());
+
+    for (entity, mut follower, movable, transform, mut velocity) in &mut followers {
+        // Leave the character to whatever else is steering it
+        let Some(destination) = follower.destination else {
+            continue;
+        };
+        let position = transform.translation;
+
+        if follower.planned_for != Some(destination) || (grid_changed && follower.use_nav_grid) {
+            let path = match &grid {
+                Some(grid) if follower.use_nav_grid => grid.find_path(position, destination),
+                _ => Some(vec![destination]),
+            };
+            let Some(path) = path else {
+                debug!("No path from {} to {}", position, destination);
+                follower.stop();
+                velocity.target = Vec3::ZERO;
+                continue;
+            };
+            follower.path = path;
+            follower.planned_for = Some(destination);
+        }
+
+        let neighbours: Vec<Vec3> = characters
+            .iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(_, transform)| transform.translation)
+            .collect();
+        velocity.target = steer_along_path(&mut follower, movable, position, &neighbours);
+    }
+}
+
+// The velocity to follow the path with, moving on to the next waypoint when reaching one
+pub fn steer_along_path(
+    follower: &mut PathFollower,
+    movable: &Movable,
+    position: Vec3,
+    neighbours: &[Vec3],
+) -> Vec3 {
+    let flat = |vector: Vec3| vector * Vec3::new(1.0, 0.0, 1.0);
+
+    while let Some(&waypoint) = follower.path.first() {
+        let last = follower.path.len() == 1;
+        let distance = flat(waypoint - position).length();
+        let reached = if last {
+            follower.arrive_distance
+        } else {
+            WAYPOINT_DISTANCE
+        };
+        if distance >= reached {
+            break;
+        }
+        follower.path.remove(0);
+    }
+    let Some(&waypoint) = follower.path.first() else {
+        follower.stop();
+        return Vec3::ZERO;
+    };
+
+    let offset = flat(waypoint - position);
+    let distance = offset.length();
+    let mut speed = if follower.run {
+        movable.run_speed
+    } else {
+        movable.walk_speed
+    };
+    if follower.path.len() == 1 {
+        speed = movable.arrival_speed(speed, distance);
+    }
+
+    // Steer away from characters that are in the way, more so the closer they are
+    let mut avoidance = Vec3::ZERO;
+    for &neighbour in neighbours {
+        let away = flat(position - neighbour);
+        let gap = away.length();
+        if gap > 1e-4 && gap < follower.avoidance_radius {
+            avoidance += away / gap * (1.0 - gap / follower.avoidance_radius);
+        }
+    }
+
+    let direction = (offset / distance + avoidance * 1.5).normalize_or_zero();
+    direction * speed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn following(path: Vec<Vec3>) -> PathFollower {
+        PathFollower {
+            destination: path.last().copied(),
+            planned_for: path.last().copied(),
+            path,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn moves_on_to_the_next_waypoint() {
+        let movable = Movable::default();
+        let mut follower = following(vec![Vec3::X, Vec3::new(1.0, 0.0, 2.0)]);
+
+        let velocity = steer_along_path(&mut follower, &movable, Vec3::X * 0.9, &[]);
+        assert_eq!(follower.path.len(), 1);
+        assert!(velocity.normalize().abs_diff_eq(Vec3::Z, 0.1));
+    }
+
+    #[test]
+    fn stops_on_arrival() {
+        let movable = Movable::default();
+        let mut follower = following(vec![Vec3::X]);
+
+        assert_eq!(
+            steer_along_path(&mut follower, &movable, Vec3::X * 0.95, &[]),
+            Vec3::ZERO
+        );
+        assert!(follower.destination.is_none());
+        assert!(follower.path.is_empty());
+    }
+
+    #[test]
+    fn steers_around_characters_in_the_way() {
+        let movable = Movable::default();
+        let mut follower = following(vec![Vec3::X * 5.0]);
+
+        let velocity = steer_along_path(
+            &mut follower,
+            &movable,
+            Vec3::ZERO,
+            &[Vec3::new(0.4, 0.0, 0.2)],
+        );
+        assert!(velocity.x > 0.0);
+        assert!(velocity.z < 0.0);
+    }
+}
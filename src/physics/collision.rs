@@ -0,0 +1,411 @@
+use bevy::prelude::*;
+
+// Upright capsule, as the segment between the centres of its bottom and top spheres
+#[derive(Clone, Copy)]
+pub struct Capsule {
+    pub bottom: Vec3,
+    pub top: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum Obstacle {
+    Box {
+        center: Vec3,
+        rotation: Quat,
+        half_extents: Vec3,
+    },
+    Capsule(Capsule),
+}
+
+// How far a capsule overlaps an obstacle, and in which direction to push it out
+#[derive(Clone, Copy)]
+pub struct Contact {
+    pub normal: Vec3,
+    pub depth: f32,
+    // Closest point on the obstacle's surface
+    pub point: Vec3,
+    // Normal of the surface that was hit. Differs from `normal` on edges and corners,
+    // where it is the most upward facing of the faces that meet there
+    pub surface_normal: Vec3,
+}
+
+pub fn capsule_contact(capsule: &Capsule, obstacle: &Obstacle) -> Option<Contact> {
+    match *obstacle {
+        Obstacle::Box {
+            center,
+            rotation,
+            half_extents,
+        } => capsule_box_contact(capsule, center, rotation, half_extents),
+        Obstacle::Capsule(other) => capsule_capsule_contact(capsule, &other),
+    }
+}
+
+fn capsule_box_contact(
+    capsule: &Capsule,
+    center: Vec3,
+    rotation: Quat,
+    half_extents: Vec3,
+) -> Option<Contact> {
+    // Work in the box's own space, where it is axis aligned
+    let inverse = rotation.inverse();
+    let bottom = inverse * (capsule.bottom - center);
+    let top = inverse * (capsule.top - center);
+
+    // Alternate between the closest point on the segment and on the box until they settle
+    let mut on_segment = closest_point_on_segment(bottom, top, Vec3::ZERO);
+    for _ in 0..4 {
+        let on_box = on_segment.clamp(-half_extents, half_extents);
+        on_segment = closest_point_on_segment(bottom, top, on_box);
+    }
+    let on_box = on_segment.clamp(-half_extents, half_extents);
+
+    let difference = on_segment - on_box;
+    let distance = difference.length();
+    if distance >= capsule.radius {
+        return None;
+    }
+    if distance > 1e-5 {
+        // Faces of the box the segment is outside of
+        let mut surface_normal = Vec3::NEG_Y;
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let outside = difference.dot(axis);
+            if outside.abs() > 1e-6 {
+                let face = rotation * (axis * outside.signum());
+                if face.y > surface_normal.y {
+                    surface_normal = face;
+                }
+            }
+        }
+        return Some(Contact {
+            normal: rotation * (difference / distance),
+            depth: capsule.radius - distance,
+            point: center + rotation * on_box,
+            surface_normal,
+        });
+    }
+
+    // The segment is inside the box, push out through the nearest face
+    let penetration = half_extents - on_segment.abs();
+    let axis = if penetration.x < penetration.y && penetration.x < penetration.z {
+        Vec3::X
+    } else if penetration.y < penetration.z {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    let sign = if on_segment.dot(axis) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let normal = rotation * (axis * sign);
+    Some(Contact {
+        normal,
+        depth: penetration.dot(axis) + capsule.radius,
+        point: center + rotation * on_segment,
+        surface_normal: normal,
+    })
+}
+
+fn capsule_capsule_contact(capsule: &Capsule, other: &Capsule) -> Option<Contact> {
+    let (on_capsule, on_other) =
+        closest_points_between_segments(capsule.bottom, capsule.top, other.bottom, other.top);
+    let difference = on_capsule - on_other;
+    let distance = difference.length();
+    let radius = capsule.radius + other.radius;
+    if distance >= radius {
+        return None;
+    }
+
+    // Exactly on top of each other, pick any sideways direction
+    let normal = if distance > 1e-5 {
+        difference / distance
+    } else {
+        Vec3::X
+    };
+    Some(Contact {
+        normal,
+        depth: radius - distance,
+        point: on_other + normal * other.radius,
+        surface_normal: normal,
+    })
+}
+
+// Where a ray hit, `distance` is along the ray
+#[derive(Clone, Copy)]
+pub struct RayHit {
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+// The closest box hit by the ray, characters are ignored.
+// `direction` has to be normalized
+pub fn cast_ray(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    obstacles: &[Obstacle],
+) -> Option<RayHit> {
+    obstacles
+        .iter()
+        .filter_map(|obstacle| ray_hit(origin, direction, obstacle))
+        .filter(|hit| hit.distance <= max_distance)
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+// Where the ray hits a single box, characters are ignored. `direction` has to be normalized
+pub fn ray_hit(origin: Vec3, direction: Vec3, obstacle: &Obstacle) -> Option<RayHit> {
+    let Obstacle::Box {
+        center,
+        rotation,
+        half_extents,
+    } = *obstacle
+    else {
+        return None;
+    };
+    ray_box_distance(origin, direction, center, rotation, half_extents).map(|(distance, normal)| {
+        RayHit {
+            distance,
+            point: origin + direction * distance,
+            normal,
+        }
+    })
+}
+
+// The closest box hit by a sphere moving along the ray, characters are ignored. The hit
+// point is the sphere's center when it touches. Starting out overlapping a box is a hit
+// at distance 0. `direction` has to be normalized
+pub fn cast_sphere(
+    origin: Vec3,
+    direction: Vec3,
+    radius: f32,
+    max_distance: f32,
+    obstacles: &[Obstacle],
+) -> Option<RayHit> {
+    // Sphere tracing, stepping as far as the closest box allows each time
+    let mut distance = 0.0;
+    for _ in 0..64 {
+        let point = origin + direction * distance;
+        let (gap, normal) = obstacles
+            .iter()
+            .filter_map(|obstacle| match *obstacle {
+                Obstacle::Box {
+                    center,
+                    rotation,
+                    half_extents,
+                } => Some(box_signed_distance(point, center, rotation, half_extents)),
+                Obstacle::Capsule(_) => None,
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))?;
+        let gap = gap - radius;
+        if gap < 1e-4 {
+            return Some(RayHit {
+                distance,
+                point,
+                normal,
+            });
+        }
+        distance += gap;
+        if distance > max_distance {
+            return None;
+        }
+    }
+    // Grazing past an edge, too slowly to tell
+    None
+}
+
+// Distance from the point to the surface of the box, negative inside, and the direction away from it
+fn box_signed_distance(
+    point: Vec3,
+    center: Vec3,
+    rotation: Quat,
+    half_extents: Vec3,
+) -> (f32, Vec3) {
+    let local = rotation.inverse() * (point - center);
+    let outside = local.abs() - half_extents;
+    let beyond = outside.max(Vec3::ZERO);
+    if beyond != Vec3::ZERO {
+        let distance = beyond.length();
+        return (distance, rotation * (beyond * local.signum() / distance));
+    }
+
+    // Inside, the nearest face is the one the point is least inside of
+    let axis = if outside.x > outside.y && outside.x > outside.z {
+        Vec3::X
+    } else if outside.y > outside.z {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    let sign = if local.dot(axis) < 0.0 { -1.0 } else { 1.0 };
+    (outside.max_element(), rotation * (axis * sign))
+}
+
+// Slab test in the box's own space. Rays starting inside the box don't hit it
+fn ray_box_distance(
+    origin: Vec3,
+    direction: Vec3,
+    center: Vec3,
+    rotation: Quat,
+    half_extents: Vec3,
+) -> Option<(f32, Vec3)> {
+    let inverse = rotation.inverse();
+    let origin = inverse * (origin - center);
+    let direction = inverse * direction;
+
+    let mut entry = 0.0;
+    let mut exit = f32::INFINITY;
+    let mut normal = Vec3::ZERO;
+    for axis in 0..3 {
+        let (start, step, extent) = (origin[axis], direction[axis], half_extents[axis]);
+        if step.abs() < 1e-8 {
+            if start.abs() > extent {
+                return None;
+            }
+            continue;
+        }
+
+        let near = (-extent.copysign(step) - start) / step;
+        let far = (extent.copysign(step) - start) / step;
+        if near > entry {
+            entry = near;
+            normal = Vec3::ZERO;
+            normal[axis] = -step.signum();
+        }
+        exit = exit.min(far);
+        if entry > exit {
+            return None;
+        }
+    }
+
+    if normal == Vec3::ZERO {
+        return None;
+    }
+    Some((entry, rotation * normal))
+}
+
+pub fn closest_point_on_segment(a: Vec3, b: Vec3, point: Vec3) -> Vec3 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared < 1e-10 {
+        return a;
+    }
+    let t = ((point - a).dot(ab) / length_squared).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+// From Real-Time Collision Detection, by Christer Ericson
+pub fn closest_points_between_segments(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (Vec3, Vec3) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+
+    let (s, t) = if a < 1e-10 && e < 1e-10 {
+        (0.0, 0.0)
+    } else if a < 1e-10 {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        if e < 1e-10 {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denominator = a * e - b * b;
+            let mut s = if denominator > 1e-10 {
+                ((b * f - c * e) / denominator).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+            (s, t)
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ground() -> Obstacle {
+        Obstacle::Box {
+            center: Vec3::new(0.0, -0.5, 0.0),
+            rotation: Quat::IDENTITY,
+            half_extents: Vec3::new(5.0, 0.5, 5.0),
+        }
+    }
+
+    #[test]
+    fn rays_hit_the_closest_box() {
+        let platform = Obstacle::Box {
+            center: Vec3::new(0.0, 0.5, 0.0),
+            rotation: Quat::IDENTITY,
+            half_extents: Vec3::new(1.0, 0.5, 1.0),
+        };
+        let hit = cast_ray(Vec3::Y * 3.0, Vec3::NEG_Y, 10.0, &[ground(), platform]).unwrap();
+
+        assert!((hit.distance - 2.0).abs() < 1e-5);
+        assert!(hit.point.abs_diff_eq(Vec3::Y, 1e-5));
+        assert_eq!(hit.normal, Vec3::Y);
+    }
+
+    #[test]
+    fn rays_miss_beyond_their_length_and_beside_boxes() {
+        assert!(cast_ray(Vec3::Y * 3.0, Vec3::NEG_Y, 2.0, &[ground()]).is_none());
+        assert!(cast_ray(Vec3::new(6.0, 3.0, 0.0), Vec3::NEG_Y, 10.0, &[ground()]).is_none());
+    }
+
+    #[test]
+    fn rays_hit_rotated_boxes() {
+        let ramp = Obstacle::Box {
+            center: Vec3::ZERO,
+            rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+            half_extents: Vec3::splat(1.0),
+        };
+        let hit = cast_ray(Vec3::Y * 3.0, Vec3::NEG_Y, 10.0, &[ramp]).unwrap();
+
+        assert!((hit.point.y - 2.0_f32.sqrt()).abs() < 1e-5);
+        assert!((hit.normal.y - 0.5_f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn spheres_stop_their_radius_away_from_boxes() {
+        let hit = cast_sphere(Vec3::Y * 3.0, Vec3::NEG_Y, 0.5, 10.0, &[ground()]).unwrap();
+
+        assert!((hit.distance - 2.5).abs() < 1e-3);
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 1e-5));
+        assert!(cast_sphere(Vec3::Y * 3.0, Vec3::NEG_Y, 0.5, 2.0, &[ground()]).is_none());
+    }
+
+    #[test]
+    fn spheres_pass_close_by_edges_that_rays_miss() {
+        // Passes 0.3 beside the ground's edge, so a sphere of 0.5 clips it but 0.2 doesn't
+        let origin = Vec3::new(5.3, 3.0, 0.0);
+        assert!(cast_ray(origin, Vec3::NEG_Y, 10.0, &[ground()]).is_none());
+        assert!(cast_sphere(origin, Vec3::NEG_Y, 0.5, 10.0, &[ground()]).is_some());
+        assert!(cast_sphere(origin, Vec3::NEG_Y, 0.2, 10.0, &[ground()]).is_none());
+
+        // Past the rounded corner, where a box grown by the radius would still be hit
+        let corner = Vec3::new(5.4, 3.0, 5.4);
+        assert!(cast_sphere(corner, Vec3::NEG_Y, 0.5, 10.0, &[ground()]).is_none());
+    }
+
+    #[test]
+    fn spheres_starting_inside_hit_straight_away() {
+        let hit = cast_sphere(Vec3::Y * 0.2, Vec3::Y, 0.5, 10.0, &[ground()]).unwrap();
+        assert_eq!(hit.distance, 0.0);
+    }
+}
@@ -0,0 +1,66 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use super::collision::{Capsule, Obstacle};
+
+// Solid box that characters collide with, positioned and rotated by the entity's Transform
+#[derive(Component, Reflect, Clone, Copy)]
+pub struct BoxCollider {
+    pub half_extents: Vec3,
+}
+
+impl BoxCollider {
+    pub fn obstacle(&self, transform: &Transform) -> Obstacle {
+        Obstacle::Box {
+            center: transform.translation,
+            rotation: transform.rotation,
+            half_extents: self.half_extents,
+        }
+    }
+}
+
+// Moves a character as an upright capsule, sliding along whatever it bumps into
+#[derive(Component, Reflect)]
+pub struct CharacterController {
+    pub radius: f32,
+    pub height: f32,
+    // Distance from the Transform origin up to the character's feet
+    pub feet_offset: f32,
+    // Highest ledge the character can walk up without jumping
+    pub step_height: f32,
+    // Steepest walkable slope, in radians
+    pub max_slope: f32,
+    pub gravity: f32,
+    // Set by gameplay code or AI, in metres per second. Only the horizontal part is used
+    pub desired_velocity: Vec3,
+    pub vertical_velocity: f32,
+    pub grounded: bool,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            radius: 0.25,
+            height: 1.0,
+            feet_offset: 0.15,
+            step_height: 0.25,
+            max_slope: PI / 4.0,
+            gravity: 9.81,
+            desired_velocity: Vec3::ZERO,
+            vertical_velocity: 0.0,
+            grounded: false,
+        }
+    }
+}
+
+impl CharacterController {
+    pub fn capsule(&self, position: Vec3) -> Capsule {
+        let feet = position + Vec3::Y * self.feet_offset;
+        Capsule {
+            bottom: feet + Vec3::Y * self.radius,
+            top: feet + Vec3::Y * (self.height - self.radius).max(self.radius),
+            radius: self.radius,
+        }
+    }
+}
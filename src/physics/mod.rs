@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+pub mod collision;
+pub mod components;
+mod systems;
+
+use components::*;
+use systems::*;
+
+use crate::GameState;
+
+// Characters are moved in this set, so anything following them can run after it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsSet;
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Register types
+            .register_type::<BoxCollider>()
+            .register_type::<CharacterController>()
+            // On update
+            .add_system(
+                move_characters
+                    .in_set(PhysicsSet)
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{advance, playing_app};
+
+    // A headless app with a small scene: ground, a wall and a falling character
+    fn test_app() -> App {
+        let mut app = playing_app();
+        app.add_plugin(PhysicsPlugin);
+        app.world.spawn((
+            BoxCollider {
+                half_extents: Vec3::new(5.0, 0.5, 5.0),
+            },
+            Transform::from_xyz(0.0, -0.5, 0.0),
+        ));
+        app.world.spawn((
+            BoxCollider {
+                half_extents: Vec3::new(0.5, 1.0, 5.0),
+            },
+            Transform::from_xyz(3.5, 1.0, 0.0),
+        ));
+        app
+    }
+
+    fn run(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds * 60.0) as usize {
+            advance(app, 1.0 / 60.0);
+        }
+    }
+
+    #[test]
+    fn character_lands_and_walks_into_the_wall() {
+        let mut app = test_app();
+        let character = app
+            .world
+            .spawn((
+                CharacterController::default(),
+                Transform::from_xyz(0.0, 1.0, 0.0),
+            ))
+            .id();
+
+        run(&mut app, 1.0);
+        let controller = app.world.get::<CharacterController>(character).unwrap();
+        let transform = app.world.get::<Transform>(character).unwrap();
+        assert!(controller.grounded);
+        assert!((transform.translation.y + controller.feet_offset).abs() < 0.01);
+
+        app.world
+            .get_mut::<CharacterController>(character)
+            .unwrap()
+            .desired_velocity = Vec3::X * 5.0;
+        run(&mut app, 2.0);
+        let controller = app.world.get::<CharacterController>(character).unwrap();
+        let transform = app.world.get::<Transform>(character).unwrap();
+        assert!((transform.translation.x - (3.0 - controller.radius)).abs() < 0.02);
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+
+use super::collision::*;
+use super::components::*;
+
+pub fn move_characters(
+    mut characters: Query<(Entity, &mut CharacterController, &mut Transform)>,
+    colliders: Query<(&BoxCollider, &Transform), Without<CharacterController>>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    let boxes: Vec<Obstacle> = colliders
+        .iter()
+        .map(|(collider, transform)| collider.obstacle(transform))
+        .collect();
+    // Characters block each other, at where they were at the start of the frame
+    let capsules: Vec<(Entity, Capsule)> = characters
+        .iter()
+        .map(|(entity, controller, transform)| (entity, controller.capsule(transform.translation)))
+        .collect();
+
+    for (entity, mut controller, mut transform) in &mut characters {
+        let mut obstacles = boxes.clone();
+        obstacles.extend(
+            capsules
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, capsule)| Obstacle::Capsule(*capsule)),
+        );
+
+        transform.translation =
+            step_character(&mut controller, transform.translation, delta, &obstacles);
+    }
+}
+
+// What a capsule ran into while moving
+#[derive(Default)]
+pub struct Collisions {
+    // Walkable ground, or the edge of a low enough step
+    pub ground: bool,
+    pub ceiling: bool,
+    // Walls, steep slopes and other characters
+    pub walls: bool,
+}
+
+// Walk, fall and land for one frame. Returns the new position
+pub fn step_character(
+    controller: &mut CharacterController,
+    position: Vec3,
+    delta: f32,
+    obstacles: &[Obstacle],
+) -> Vec3 {
+    let was_grounded = controller.grounded;
+
+    // Walk
+    let motion = controller.desired_velocity * Vec3::new(1.0, 0.0, 1.0) * delta;
+    let (walked, _) = move_and_slide(controller, position, motion, obstacles);
+
+    // Fall
+    controller.vertical_velocity -= controller.gravity * delta;
+    let fall = Vec3::Y * controller.vertical_velocity * delta;
+    let (mut new_position, collisions) = move_and_slide(controller, walked, fall, obstacles);
+    let mut grounded = collisions.ground;
+
+    // Stick to the ground when walking down steps and slopes
+    if !grounded && was_grounded && controller.vertical_velocity <= 0.0 {
+        let down = Vec3::NEG_Y * controller.step_height;
+        let (snapped, collisions) = move_and_slide(controller, new_position, down, obstacles);
+        if collisions.ground {
+            new_position = snapped;
+            grounded = true;
+        }
+    }
+
+    if (grounded && controller.vertical_velocity < 0.0)
+        || (collisions.ceiling && controller.vertical_velocity > 0.0)
+    {
+        controller.vertical_velocity = 0.0;
+    }
+    controller.grounded = grounded;
+
+    new_position
+}
+
+// Move the capsule, pushing it back out of anything it runs into
+pub fn move_and_slide(
+    controller: &CharacterController,
+    position: Vec3,
+    motion: Vec3,
+    obstacles: &[Obstacle],
+) -> (Vec3, Collisions) {
+    let mut position = position;
+    let mut collisions = Collisions::default();
+
+    // Small enough steps to not tunnel through anything
+    let steps = (motion.length() / (controller.radius * 0.5))
+        .ceil()
+        .max(1.0) as usize;
+    let step = motion / steps as f32;
+    for _ in 0..steps {
+        position += step;
+
+        for _ in 0..4 {
+            let mut resolved = true;
+            for obstacle in obstacles {
+                let capsule = controller.capsule(position);
+                let Some(contact) = capsule_contact(&capsule, obstacle) else {
+                    continue;
+                };
+                position += push_out(controller, position, &contact, &mut collisions);
+                resolved = false;
+            }
+            if resolved {
+                break;
+            }
+        }
+    }
+
+    (position, collisions)
+}
+
+fn push_out(
+    controller: &CharacterController,
+    position: Vec3,
+    contact: &Contact,
+    collisions: &mut Collisions,
+) -> Vec3 {
+    let min_walkable = controller.max_slope.cos();
+    let normal = contact.normal;
+    let feet = position.y + controller.feet_offset;
+
+    // Walkable ground
+    if normal.y >= min_walkable {
+        collisions.ground = true;
+        return normal * contact.depth;
+    }
+
+    // Ceilings and overhangs
+    if normal.y < 0.0 {
+        collisions.ceiling = true;
+        return normal * contact.depth;
+    }
+
+    // Edges of low steps, which the capsule rides up onto
+    if contact.surface_normal.y >= min_walkable && contact.point.y - feet <= controller.step_height
+    {
+        collisions.ground = true;
+        return Vec3::Y * (contact.depth / normal.y).min(controller.step_height);
+    }
+
+    // Walls and steep slopes, only pushed away from sideways so they can't be climbed
+    collisions.walls = true;
+    let sideways = normal * Vec3::new(1.0, 0.0, 1.0);
+    let length = sideways.length();
+    if length < 1e-5 {
+        return normal * contact.depth;
+    }
+    (sideways / length) * (contact.depth / length).min(contact.depth * 4.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const DELTA: f32 = 1.0 / 60.0;
+
+    fn ground() -> Obstacle {
+        Obstacle::Box {
+            center: Vec3::new(0.0, -0.5, 0.0),
+            rotation: Quat::IDENTITY,
+            half_extents: Vec3::new(50.0, 0.5, 50.0),
+        }
+    }
+
+    // Box resting on the ground, with its near face at x = 1
+    fn block(height: f32) -> Obstacle {
+        Obstacle::Box {
+            center: Vec3::new(2.0, height * 0.5, 0.0),
+            rotation: Quat::IDENTITY,
+            half_extents: Vec3::new(1.0, height * 0.5, 1.0),
+        }
+    }
+
+    // Ramp rising towards +x, starting at x = 0
+    fn ramp(angle: f32) -> Obstacle {
+        let half_extents = Vec3::new(4.0, 0.5, 2.0);
+        let rotation = Quat::from_rotation_z(angle);
+        let center = Vec3::new(
+            half_extents.x * angle.cos(),
+            half_extents.x * angle.sin(),
+            0.0,
+        ) + rotation * Vec3::new(0.0, -half_extents.y, 0.0);
+        Obstacle::Box {
+            center,
+            rotation,
+            half_extents,
+        }
+    }
+
+    // Feet on the ground at the given spot
+    fn standing_at(x: f32, z: f32) -> Vec3 {
+        Vec3::new(x, -CharacterController::default().feet_offset, z)
+    }
+
+    fn simulate(
+        controller: &mut CharacterController,
+        mut position: Vec3,
+        seconds: f32,
+        obstacles: &[Obstacle],
+    ) -> Vec3 {
+        for _ in 0..(seconds / DELTA) as usize {
+            position = step_character(controller, position, DELTA, obstacles);
+        }
+        position
+    }
+
+    fn walking(velocity: Vec3) -> CharacterController {
+        CharacterController {
+            desired_velocity: velocity,
+            grounded: true,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_ground() {
+        let mut controller = CharacterController::default();
+        let position = simulate(&mut controller, Vec3::new(0.0, 2.0, 0.0), 2.0, &[ground()]);
+
+        assert!(controller.grounded);
+        assert!((position.y - standing_at(0.0, 0.0).y).abs() < 0.01);
+        assert_eq!(controller.vertical_velocity, 0.0);
+    }
+
+    #[test]
+    fn walks_on_flat_ground() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let position = simulate(&mut controller, standing_at(0.0, 0.0), 1.0, &[ground()]);
+
+        assert!(controller.grounded);
+        assert!((position.x - 2.0).abs() < 0.05);
+        assert!((position.y - standing_at(0.0, 0.0).y).abs() < 0.01);
+    }
+
+    #[test]
+    fn walls_block_the_way() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let obstacles = [ground(), block(2.0)];
+        let position = simulate(&mut controller, standing_at(0.0, 0.0), 1.0, &obstacles);
+
+        assert!(position.x <= 1.0 - controller.radius + 0.01);
+        assert!(position.x > 1.0 - controller.radius - 0.05);
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        let mut controller = walking(Vec3::new(2.0, 0.0, 2.0));
+        let obstacles = [ground(), block(2.0)];
+        let position = simulate(&mut controller, standing_at(0.0, -0.5), 0.5, &obstacles);
+
+        assert!(position.x <= 1.0 - controller.radius + 0.01);
+        assert!(position.z > 0.3);
+    }
+
+    #[test]
+    fn steps_up_low_ledges() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let obstacles = [ground(), block(0.2)];
+        let position = simulate(&mut controller, standing_at(0.0, 0.0), 1.0, &obstacles);
+
+        assert!(controller.grounded);
+        assert!(position.x > 1.5);
+        assert!((position.y - (0.2 - controller.feet_offset)).abs() < 0.01);
+    }
+
+    #[test]
+    fn does_not_step_up_high_ledges() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let obstacles = [ground(), block(0.4)];
+        let position = simulate(&mut controller, standing_at(0.0, 0.0), 1.0, &obstacles);
+
+        assert!(position.x < 1.0);
+    }
+
+    #[test]
+    fn stays_grounded_walking_down_steps() {
+        let mut controller = walking(Vec3::NEG_X * 2.0);
+        let obstacles = [ground(), block(0.2)];
+        let start = standing_at(2.0, 0.0) + Vec3::Y * 0.2;
+        let mut position = start;
+        for _ in 0..60 {
+            position = step_character(&mut controller, position, DELTA, &obstacles);
+            assert!(controller.grounded);
+        }
+
+        assert!(position.x < 0.5);
+        assert!((position.y - standing_at(0.0, 0.0).y).abs() < 0.01);
+    }
+
+    #[test]
+    fn walks_up_gentle_slopes() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let obstacles = [ground(), ramp(PI / 9.0)];
+        let position = simulate(&mut controller, standing_at(-1.0, 0.0), 2.0, &obstacles);
+
+        assert!(controller.grounded);
+        assert!(position.x > 2.0);
+        assert!(position.y > 0.5);
+    }
+
+    #[test]
+    fn can_not_climb_steep_slopes() {
+        let mut controller = walking(Vec3::X * 2.0);
+        let obstacles = [ground(), ramp(PI / 3.0)];
+        let position = simulate(&mut controller, standing_at(-1.0, 0.0), 2.0, &obstacles);
+
+        assert!(position.x < 0.3);
+        assert!(position.y < 0.3);
+    }
+
+    #[test]
+    fn characters_do_not_overlap() {
+        let other = CharacterController::default();
+        let other_capsule = other.capsule(standing_at(1.0, 0.0));
+        let obstacles = [ground(), Obstacle::Capsule(other_capsule)];
+
+        let mut controller = walking(Vec3::X * 2.0);
+        let position = simulate(&mut controller, standing_at(0.0, 0.0), 1.0, &obstacles);
+
+        let distance = (position - standing_at(1.0, 0.0)).length();
+        assert!(distance >= controller.radius + other.radius - 0.01);
+    }
+}
@@ -0,0 +1,63 @@
+use std::any::TypeId;
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use super::format::Migration;
+use super::migrations;
+use crate::animation::components::AnimatedCharacter;
+use crate::camera::components::FollowCamera;
+use crate::character::components::Velocity;
+use crate::Atmosphere;
+
+// Marks an entity to be written to save files. Entities are matched up by their Name
+// when loading, so it needs one
+#[derive(Component, Default)]
+pub struct Persistent;
+
+// Which reflected types go into a save file, and how to upgrade old save files
+#[derive(Resource)]
+pub struct SaveRegistry {
+    pub components: Vec<TypeId>,
+    pub resources: Vec<TypeId>,
+    // By the version they upgrade from
+    pub migrations: BTreeMap<u32, Migration>,
+}
+
+impl Default for SaveRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            components: Vec::new(),
+            resources: Vec::new(),
+            migrations: BTreeMap::new(),
+        };
+        registry
+            .save_component::<Transform>()
+            .save_component::<Velocity>()
+            .save_component::<AnimatedCharacter>()
+            .save_component::<FollowCamera>()
+            .save_resource::<Atmosphere>()
+            .add_migration(1, migrations::split_follow_camera_speed);
+        registry
+    }
+}
+
+impl SaveRegistry {
+    // The type has to be registered with `#[reflect(Component)]`
+    pub fn save_component<T: Component + Reflect>(&mut self) -> &mut Self {
+        self.components.push(TypeId::of::<T>());
+        self
+    }
+
+    // The type has to be registered with `#[reflect(Resource)]`
+    pub fn save_resource<T: Resource + Reflect>(&mut self) -> &mut Self {
+        self.resources.push(TypeId::of::<T>());
+        self
+    }
+
+    // Upgrade save files of `from_version` to the next version
+    pub fn add_migration(&mut self, from_version: u32, migration: Migration) -> &mut Self {
+        self.migrations.insert(from_version, migration);
+        self
+    }
+}
@@ -0,0 +1,8 @@
+use std::path::PathBuf;
+
+// Write the Persistent entities and saved resources to a file, as JSON if the path
+// ends in `.json` and RON otherwise
+pub struct SaveWorld(pub PathBuf);
+
+// Apply a save file written by SaveWorld to the world
+pub struct LoadWorld(pub PathBuf);
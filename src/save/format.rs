@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Bump this, and add a migration from the previous version, whenever saved types change
+// in a way old save files can't be read as
+pub const SAVE_VERSION: u32 = 2;
+
+// Upgrades a save file by one version, by editing its values in place
+pub type Migration = fn(&mut SaveFile);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveFormat {
+    Ron,
+    Json,
+}
+
+impl SaveFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension() {
+            Some(extension) if extension == "json" => SaveFormat::Json,
+            _ => SaveFormat::Ron,
+        }
+    }
+}
+
+// Reflected values are kept as plain data, so migrations can rename and reshape them
+// before they are read back into their types
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SaveFile {
+    pub version: u32,
+    // By type name
+    pub resources: BTreeMap<String, Value>,
+    pub entities: Vec<SavedEntity>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SavedEntity {
+    pub name: String,
+    // By type name
+    pub components: BTreeMap<String, Value>,
+}
+
+impl Default for SaveFile {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            resources: BTreeMap::new(),
+            entities: Vec::new(),
+        }
+    }
+}
+
+impl SaveFile {
+    pub fn to_text(&self, format: SaveFormat) -> Result<String, Box<dyn Error>> {
+        Ok(match format {
+            SaveFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?,
+            SaveFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    pub fn from_text(text: &str, format: SaveFormat) -> Result<Self, Box<dyn Error>> {
+        Ok(match format {
+            SaveFormat::Ron => ron::de::from_str(text)?,
+            SaveFormat::Json => serde_json::from_str(text)?,
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, self.to_text(SaveFormat::from_path(path))?)?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        Self::from_text(&fs::read_to_string(path)?, SaveFormat::from_path(path))
+    }
+
+    // Run the migrations one version at a time, up to `target_version`
+    pub fn migrate(
+        &mut self,
+        migrations: &BTreeMap<u32, Migration>,
+        target_version: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.version > target_version {
+            return Err(format!(
+                "save file version {} is newer than the game's version {}",
+                self.version, target_version
+            )
+            .into());
+        }
+        while self.version < target_version {
+            let migration = migrations
+                .get(&self.version)
+                .ok_or_else(|| format!("no migration from save file version {}", self.version))?;
+            migration(self);
+            self.version += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn save_file(version: u32) -> SaveFile {
+        SaveFile {
+            version,
+            resources: BTreeMap::from([("game::Weather".to_string(), json!({ "rain": 0.5 }))]),
+            entities: vec![SavedEntity {
+                name: "Player".to_string(),
+                components: BTreeMap::from([(
+                    "game::Health".to_string(),
+                    json!({ "hp": 3, "status": { "Poisoned": 2.5 }, "target": null }),
+                )]),
+            }],
+        }
+    }
+
+    #[test]
+    fn save_files_survive_both_formats() {
+        let save = save_file(SAVE_VERSION);
+        for format in [SaveFormat::Ron, SaveFormat::Json] {
+            let text = save.to_text(format).unwrap();
+            assert_eq!(SaveFile::from_text(&text, format).unwrap(), save);
+        }
+        assert_eq!(SaveFormat::from_path("saves/a.json"), SaveFormat::Json);
+        assert_eq!(SaveFormat::from_path("saves/a.ron"), SaveFormat::Ron);
+    }
+
+    #[test]
+    fn migrations_run_in_order_up_to_the_target() {
+        fn rename_hp(save: &mut SaveFile) {
+            for entity in save.entities.iter_mut() {
+                if let Some(health) = entity.components.get_mut("game::Health") {
+                    health["current"] = health["hp"].take();
+                }
+            }
+        }
+        fn double_current(save: &mut SaveFile) {
+            for entity in save.entities.iter_mut() {
+                if let Some(health) = entity.components.get_mut("game::Health") {
+                    health["current"] = json!(health["current"].as_i64().unwrap() * 2);
+                }
+            }
+        }
+        let migrations = BTreeMap::from([(1, rename_hp as Migration), (2, double_current)]);
+
+        let mut save = save_file(1);
+        save.migrate(&migrations, 3).unwrap();
+
+        assert_eq!(save.version, 3);
+        assert_eq!(save.entities[0].components["game::Health"]["current"], 6);
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let migrations = BTreeMap::new();
+        assert!(save_file(2).migrate(&migrations, 1).is_err());
+        assert!(save_file(1).migrate(&migrations, 2).is_err());
+        assert!(save_file(1).migrate(&migrations, 1).is_ok());
+    }
+}
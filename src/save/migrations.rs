@@ -0,0 +1,21 @@
+use super::format::SaveFile;
+
+// Version 2 replaced the follow camera's `speed_transition` with a stiffness each for
+// its position, rotation and zoom
+pub fn split_follow_camera_speed(save: &mut SaveFile) {
+    for entity in save.entities.iter_mut() {
+        for (type_name, component) in entity.components.iter_mut() {
+            if !type_name.ends_with("::FollowCamera") {
+                continue;
+            }
+            let Some(fields) = component.as_object_mut() else {
+                continue;
+            };
+            if let Some(speed) = fields.remove("speed_transition") {
+                fields.insert("position_stiffness".to_string(), speed.clone());
+                fields.insert("rotation_stiffness".to_string(), speed.clone());
+                fields.insert("zoom_stiffness".to_string(), speed);
+            }
+        }
+    }
+}
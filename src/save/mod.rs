@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+
+pub mod components;
+pub mod events;
+pub mod format;
+pub mod migrations;
+mod systems;
+
+use components::*;
+use events::*;
+use systems::*;
+
+use crate::GameState;
+
+pub const QUICK_SAVE_PATH: &str = "saves/quicksave.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveRegistry>()
+            // Events
+            .add_event::<SaveWorld>()
+            .add_event::<LoadWorld>()
+            // On update
+            .add_systems(
+                (quick_save_and_load, save_world, load_world)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+
+    use super::format::SaveFormat;
+    use super::systems::{apply_save_file, build_save_file};
+    use super::*;
+    use crate::animation::components::{AnimatedCharacter, AnimationState, Direction};
+    use crate::animation::AnimationPlugin;
+    use crate::camera::components::FollowCamera;
+    use crate::camera::smoothing::FollowSmoothing;
+    use crate::character::components::Velocity;
+    use crate::testing::headless_app;
+    use crate::Atmosphere;
+
+    fn test_app() -> App {
+        let mut app = headless_app();
+        app.add_plugin(TransformPlugin)
+            .add_plugin(AssetPlugin::default())
+            .add_state::<GameState>()
+            .add_plugin(AnimationPlugin)
+            .register_type::<Velocity>()
+            .register_type::<FollowCamera>()
+            .register_type::<Atmosphere>()
+            .init_resource::<Atmosphere>()
+            .add_plugin(SavePlugin);
+        app
+    }
+
+    fn spawn_character(app: &mut App, name: &str) -> Entity {
+        app.world
+            .spawn((
+                Name::new(name.to_string()),
+                Persistent,
+                Transform::default(),
+                AnimatedCharacter::default(),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn saved_state_is_loaded_back() {
+        let mut app = test_app();
+        let player = spawn_character(&mut app, "Player");
+        let camera = app
+            .world
+            .spawn((Name::new("Camera"), Persistent, FollowCamera::default()))
+            .id();
+        // Not Persistent, so left alone
+        let wall = app
+            .world
+            .spawn((Name::new("Wall"), Transform::from_xyz(5.0, 0.0, 0.0)))
+            .id();
+
+        app.world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(1.0, 2.0, 3.0);
+        {
+            let mut animated_character = app.world.get_mut::<AnimatedCharacter>(player).unwrap();
+            animated_character.heading = Vec3::X;
+            animated_character.direction = Direction::Left;
+            animated_character.animation_state = AnimationState::Run;
+            animated_character.queued_state = Some(AnimationState::Idle);
+        }
+        app.world.get_mut::<FollowCamera>(camera).unwrap().zoom = 12.0;
+        app.world.resource_mut::<Atmosphere>().preset = 3;
+
+        // Through text, as it would be on disk
+        let text = build_save_file(&app.world)
+            .unwrap()
+            .to_text(SaveFormat::Ron)
+            .unwrap();
+        assert_eq!(build_save_file(&app.world).unwrap().entities.len(), 2);
+
+        app.world.entity_mut(player).insert((
+            Transform::default(),
+            AnimatedCharacter::default(),
+            Velocity {
+                linear: Vec3::X,
+                target: Vec3::X,
+            },
+        ));
+        {
+            let mut follow_camera = app.world.get_mut::<FollowCamera>(camera).unwrap();
+            follow_camera.zoom = 4.0;
+            follow_camera.smoothing = Some(FollowSmoothing::new(&follow_camera, Vec3::ZERO));
+            follow_camera.dead_zone_center = Some(Vec3::ZERO);
+            follow_camera.collision_distance = 1.0;
+        }
+        app.world
+            .get_mut::<AnimatedCharacter>(player)
+            .unwrap()
+            .last_position = Some(Vec3::ZERO);
+        app.world.get_mut::<Transform>(wall).unwrap().translation = Vec3::ZERO;
+        app.world.resource_mut::<Atmosphere>().preset = 0;
+
+        let save = format::SaveFile::from_text(&text, SaveFormat::Ron).unwrap();
+        apply_save_file(&mut app.world, save).unwrap();
+
+        let transform = app.world.get::<Transform>(player).unwrap();
+        assert_eq!(transform.translation, Vec3::new(1.0, 2.0, 3.0));
+        let animated_character = app.world.get::<AnimatedCharacter>(player).unwrap();
+        assert_eq!(animated_character.heading, Vec3::X);
+        assert!(animated_character.direction == Direction::Left);
+        assert!(animated_character.animation_state == AnimationState::Run);
+        assert!(animated_character.queued_state == Some(AnimationState::Idle));
+        // Components that weren't saved keep their values
+        assert_eq!(app.world.get::<Velocity>(player).unwrap().linear, Vec3::X);
+        assert_eq!(app.world.get::<FollowCamera>(camera).unwrap().zoom, 12.0);
+        // Unless they're measured from where things were before loading
+        let follow_camera = app.world.get::<FollowCamera>(camera).unwrap();
+        assert!(follow_camera.smoothing.is_none());
+        assert!(follow_camera.dead_zone_center.is_none());
+        assert_eq!(follow_camera.collision_distance, f32::MAX);
+        assert!(animated_character.last_position.is_none());
+        assert_eq!(
+            app.world.get::<Transform>(wall).unwrap().translation,
+            Vec3::ZERO
+        );
+        assert_eq!(app.world.resource::<Atmosphere>().preset, 3);
+    }
+
+    #[test]
+    fn old_save_files_are_migrated() {
+        let mut app = test_app();
+        let camera = app
+            .world
+            .spawn((Name::new("Camera"), Persistent, FollowCamera::default()))
+            .id();
+        // As version 1 wrote it, with a single speed for all smoothing
+        let mut save = build_save_file(&app.world).unwrap();
+        save.version = 1;
+        let fields = save.entities[0]
+            .components
+            .values_mut()
+            .next()
+            .unwrap()
+            .as_object_mut()
+            .unwrap();
+        for field in ["position_stiffness", "rotation_stiffness", "zoom_stiffness"] {
+            fields.remove(field);
+        }
+        fields.insert("speed_transition".to_string(), serde_json::json!(6.0));
+        // Not left at its value from before loading
+        app.world
+            .get_mut::<FollowCamera>(camera)
+            .unwrap()
+            .zoom_stiffness = 1.0;
+
+        apply_save_file(&mut app.world, save).unwrap();
+
+        let follow_camera = app.world.get::<FollowCamera>(camera).unwrap();
+        assert_eq!(follow_camera.position_stiffness, 6.0);
+        assert_eq!(follow_camera.rotation_stiffness, 6.0);
+        assert_eq!(follow_camera.zoom_stiffness, 6.0);
+    }
+
+    #[test]
+    fn entities_missing_from_the_world_are_skipped() {
+        let mut app = test_app();
+        let player = spawn_character(&mut app, "Player");
+        spawn_character(&mut app, "Villager");
+        let save = build_save_file(&app.world).unwrap();
+
+        app.world.despawn(player);
+        assert!(apply_save_file(&mut app.world, save).is_ok());
+    }
+
+    #[test]
+    fn broken_saves_change_nothing() {
+        let mut app = test_app();
+        let player = spawn_character(&mut app, "Player");
+        app.world.resource_mut::<Atmosphere>().preset = 3;
+        let mut save = build_save_file(&app.world).unwrap();
+        save.entities[0]
+            .components
+            .insert(type_name::<Transform>().to_string(), "broken".into());
+
+        app.world.resource_mut::<Atmosphere>().preset = 0;
+        app.world
+            .get_mut::<AnimatedCharacter>(player)
+            .unwrap()
+            .heading = Vec3::X;
+        assert!(apply_save_file(&mut app.world, save).is_err());
+
+        assert_eq!(app.world.resource::<Atmosphere>().preset, 0);
+        assert_eq!(
+            app.world.get::<AnimatedCharacter>(player).unwrap().heading,
+            Vec3::X
+        );
+    }
+}
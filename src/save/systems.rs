@@ -0,0 +1,201 @@
+use std::error::Error;
+
+use bevy::prelude::*;
+use bevy::reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer};
+use bevy::reflect::TypeRegistration;
+use bevy::utils::HashMap;
+use serde::de::DeserializeSeed;
+use serde_json::Value;
+
+use super::components::*;
+use super::events::*;
+use super::format::*;
+use super::QUICK_SAVE_PATH;
+use crate::animation::components::AnimatedCharacter;
+use crate::camera::components::FollowCamera;
+use crate::input::actions::{Action, ActionState};
+
+pub fn quick_save_and_load(
+    actions: Res<ActionState>,
+    mut save_events: EventWriter<SaveWorld>,
+    mut load_events: EventWriter<LoadWorld>,
+) {
+    if actions.just_pressed(Action::QuickSave) {
+        save_events.send(SaveWorld(QUICK_SAVE_PATH.into()));
+    }
+    if actions.just_pressed(Action::QuickLoad) {
+        load_events.send(LoadWorld(QUICK_SAVE_PATH.into()));
+    }
+}
+
+pub fn save_world(world: &mut World) {
+    let paths: Vec<_> = world
+        .resource_mut::<Events<SaveWorld>>()
+        .drain()
+        .map(|event| event.0)
+        .collect();
+    for path in paths {
+        match build_save_file(world).and_then(|save| save.write(&path)) {
+            Ok(()) => info!("Saved the world to '{}'", path.display()),
+            Err(error) => warn!("Could not save to '{}': {}", path.display(), error),
+        }
+    }
+}
+
+pub fn load_world(world: &mut World) {
+    let paths: Vec<_> = world
+        .resource_mut::<Events<LoadWorld>>()
+        .drain()
+        .map(|event| event.0)
+        .collect();
+    for path in paths {
+        match SaveFile::read(&path).and_then(|save| apply_save_file(world, save)) {
+            Ok(()) => info!("Loaded the world from '{}'", path.display()),
+            Err(error) => warn!("Could not load '{}': {}", path.display(), error),
+        }
+    }
+}
+
+// Reflect the saved resources, and the saved components of Persistent entities
+pub fn build_save_file(world: &World) -> Result<SaveFile, Box<dyn Error>> {
+    let save_registry = world.resource::<SaveRegistry>();
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+    let mut save = SaveFile::default();
+
+    for &type_id in save_registry.resources.iter() {
+        let registration = type_registry
+            .get(type_id)
+            .ok_or("a saved resource type isn't registered")?;
+        if let Some(resource) = reflect_data::<ReflectResource>(registration)?.reflect(world) {
+            save.resources.insert(
+                registration.type_name().to_string(),
+                serde_json::to_value(TypedReflectSerializer::new(resource, &type_registry))?,
+            );
+        }
+    }
+
+    let component_registrations = save_registry
+        .components
+        .iter()
+        .map(|&type_id| {
+            type_registry
+                .get(type_id)
+                .ok_or_else(|| "a saved component type isn't registered".to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for entity in world.iter_entities() {
+        if !entity.contains::<Persistent>() {
+            continue;
+        }
+        let Some(name) = entity.get::<Name>() else {
+            warn!(
+                "Not saving Persistent entity {:?}, it has no Name",
+                entity.id()
+            );
+            continue;
+        };
+
+        let mut saved = SavedEntity {
+            name: name.to_string(),
+            components: default(),
+        };
+        for registration in component_registrations.iter() {
+            if let Some(component) = reflect_data::<ReflectComponent>(registration)?.reflect(entity)
+            {
+                saved.components.insert(
+                    registration.type_name().to_string(),
+                    serde_json::to_value(TypedReflectSerializer::new(component, &type_registry))?,
+                );
+            }
+        }
+        save.entities.push(saved);
+    }
+    // Keep save files stable between saves, to diff them
+    save.entities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(save)
+}
+
+// Migrate the save file, then apply its values to the Persistent entities with the same names.
+// Entities that aren't in the world any more are skipped
+pub fn apply_save_file(world: &mut World, mut save: SaveFile) -> Result<(), Box<dyn Error>> {
+    save.migrate(&world.resource::<SaveRegistry>().migrations, SAVE_VERSION)?;
+
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+    let read = |type_name: &str, value: Value| -> Result<_, Box<dyn Error>> {
+        let registration = type_registry
+            .get_with_name(type_name)
+            .ok_or_else(|| format!("{} isn't registered", type_name))?;
+        let reflected = TypedReflectDeserializer::new(registration, &type_registry)
+            .deserialize(value)
+            .map_err(|error| format!("{}: {}", type_name, error))?;
+        Ok((registration, reflected))
+    };
+
+    // Read everything before changing anything, so a broken save leaves the world as it was
+    let mut resources = Vec::new();
+    for (type_name, value) in save.resources {
+        let (registration, resource) = read(&type_name, value)?;
+        resources.push((
+            reflect_data::<ReflectResource>(registration)?.clone(),
+            resource,
+        ));
+    }
+
+    let mut entities = HashMap::new();
+    for entity in world.iter_entities() {
+        if let (true, Some(name)) = (entity.contains::<Persistent>(), entity.get::<Name>()) {
+            entities.insert(name.to_string(), entity.id());
+        }
+    }
+    let mut components = Vec::new();
+    for saved in save.entities {
+        let Some(&entity) = entities.get(&saved.name) else {
+            warn!(
+                "Skipping saved entity '{}', it isn't in the world",
+                saved.name
+            );
+            continue;
+        };
+        for (type_name, value) in saved.components {
+            let (registration, component) = read(&type_name, value)?;
+            components.push((
+                entity,
+                reflect_data::<ReflectComponent>(registration)?.clone(),
+                component,
+            ));
+        }
+    }
+
+    for (reflect_resource, resource) in resources {
+        reflect_resource.apply_or_insert(world, &*resource);
+    }
+    for (entity, reflect_component, component) in components {
+        reflect_component.apply_or_insert(&mut world.entity_mut(entity), &*component);
+    }
+
+    // What isn't saved is measured from the old positions, start it over from the loaded ones
+    for mut follow_camera in world.query::<&mut FollowCamera>().iter_mut(world) {
+        follow_camera.smoothing = None;
+        follow_camera.dead_zone_center = None;
+        follow_camera.collision_distance = f32::MAX;
+    }
+    for mut animated_character in world.query::<&mut AnimatedCharacter>().iter_mut(world) {
+        animated_character.last_position = None;
+    }
+
+    Ok(())
+}
+
+fn reflect_data<T: bevy::reflect::TypeData>(
+    registration: &TypeRegistration,
+) -> Result<&T, Box<dyn Error>> {
+    registration.data::<T>().ok_or_else(|| {
+        format!(
+            "{} isn't reflected as a component or resource",
+            registration.type_name()
+        )
+        .into()
+    })
+}
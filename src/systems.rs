@@ -2,6 +2,8 @@ use std::f32::consts::PI;
 
 use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
 
+use crate::physics::components::BoxCollider;
+
 pub fn spawn_basic_scene(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -15,6 +17,59 @@ pub fn spawn_basic_scene(
             ..default()
         })
         .insert(Name::new("Ground"));
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+            0.0, -0.5, 0.0,
+        )))
+        .insert(BoxCollider {
+            half_extents: Vec3::new(5.0, 0.5, 5.0),
+        })
+        .insert(Name::new("Ground Collider"));
+
+    // Invisible walls around the edge of the ground
+    for (position, half_extents) in [
+        (Vec3::new(5.5, 1.0, 0.0), Vec3::new(0.5, 1.0, 6.0)),
+        (Vec3::new(-5.5, 1.0, 0.0), Vec3::new(0.5, 1.0, 6.0)),
+        (Vec3::new(0.0, 1.0, 5.5), Vec3::new(6.0, 1.0, 0.5)),
+        (Vec3::new(0.0, 1.0, -5.5), Vec3::new(6.0, 1.0, 0.5)),
+    ] {
+        commands
+            .spawn(SpatialBundle::from_transform(Transform::from_translation(
+                position,
+            )))
+            .insert(BoxCollider { half_extents })
+            .insert(Name::new("Boundary"));
+    }
+
+    // A crate to walk around and a low platform to step onto
+    for (name, position, half_extents, color) in [
+        (
+            "Crate",
+            Vec3::new(3.0, 0.4, -2.0),
+            Vec3::splat(0.4),
+            Color::rgb(0.55, 0.4, 0.25),
+        ),
+        (
+            "Platform",
+            Vec3::new(-3.0, 0.1, 2.5),
+            Vec3::new(1.0, 0.1, 1.0),
+            Color::rgb(0.6, 0.6, 0.55),
+        ),
+    ] {
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::new(
+                    half_extents.x * 2.0,
+                    half_extents.y * 2.0,
+                    half_extents.z * 2.0,
+                ))),
+                material: materials.add(color.into()),
+                transform: Transform::from_translation(position),
+                ..default()
+            })
+            .insert(BoxCollider { half_extents })
+            .insert(Name::new(name));
+    }
 
     /*
     // Light
@@ -0,0 +1,31 @@
+// Headless apps for the plugin tests, with time under the test's control
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimePlugin;
+
+use crate::GameState;
+
+// Time only moves on when the test calls `advance`
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins.build().disable::<TimePlugin>())
+        .init_resource::<Time>();
+    app
+}
+
+// Runs the systems added to OnUpdate(GameState::Playing), from the first update
+pub fn playing_app() -> App {
+    let mut app = headless_app();
+    app.add_state::<GameState>()
+        .insert_resource(NextState(Some(GameState::Playing)));
+    app
+}
+
+// Run one update, `seconds` after the last one
+pub fn advance(app: &mut App, seconds: f32) {
+    let mut time = app.world.resource_mut::<Time>();
+    let last_update = time.last_update().unwrap_or_else(|| time.startup());
+    time.update_with_instant(last_update + Duration::from_secs_f32(seconds));
+    app.update();
+}